@@ -0,0 +1,291 @@
+use image::codecs::gif::{GifEncoder, Repeat};
+use std::fs::File;
+use std::io::{BufWriter, Write};
+
+use crate::error::MandelbrotError;
+use crate::render::{render, RenderParams};
+use crate::util;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Keyframe {
+    pub time: f32,
+    pub center: (f32, f32),
+    pub radius: f32,
+    pub iterations: u32,
+    pub palette_shift: f32
+}
+
+/// Parses a keyframe file. Each non-empty line that does not start with `#`
+/// holds whitespace separated values:
+///
+/// ```text
+/// # time  re     im    radius  iterations  [palette-shift]
+/// 0.0     -0.75  0.0   3.0     64
+/// 4.0     -0.75  0.1   0.05    256         0.5
+/// ```
+///
+/// Keyframes must be listed in strictly increasing order of time.
+pub fn parse_keyframes(input: &str) -> Result<Vec<Keyframe>, MandelbrotError> {
+    let mut keyframes: Vec<Keyframe> = Vec::new();
+
+    for (number, line) in input.lines().enumerate() {
+        let line = line.trim();
+
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+
+        let fields: Vec<&str> = line.split_whitespace().collect();
+
+        if fields.len() != 5 && fields.len() != 6 {
+            return Err(MandelbrotError::Parse(
+                format!("keyframe on line {}, expected 5 or 6 fields but found {}", number + 1, fields.len())
+            ));
+        }
+
+        let float = |index: usize| fields[index].parse::<f32>().map_err(|_| {
+            MandelbrotError::Parse(format!("'{}' on line {} of keyframe file", fields[index], number + 1))
+        });
+
+        let keyframe = Keyframe {
+            time: float(0)?,
+            center: (float(1)?, float(2)?),
+            radius: float(3)?,
+            iterations: fields[4].parse().map_err(|_| {
+                MandelbrotError::Parse(format!("'{}' on line {} of keyframe file", fields[4], number + 1))
+            })?,
+            palette_shift: if fields.len() == 6 { float(5)? } else { 0.0 }
+        };
+
+        if keyframe.radius <= 0.0 {
+            return Err(MandelbrotError::InvalidParameter(
+                format!("keyframe on line {} has a non-positive radius", number + 1)
+            ));
+        }
+
+        if let Some(previous) = keyframes.last() {
+            if keyframe.time <= previous.time {
+                return Err(MandelbrotError::InvalidParameter(
+                    format!("keyframe on line {} is not later than the one before it", number + 1)
+                ));
+            }
+        }
+
+        keyframes.push(keyframe);
+    }
+
+    if keyframes.is_empty() {
+        return Err(MandelbrotError::InvalidParameter("keyframe file contains no keyframes".to_string()));
+    }
+
+    Ok(keyframes)
+}
+
+fn ease_in_out(t: f32) -> f32 {
+    t * t * (3.0 - 2.0 * t)
+}
+
+/// Interpolates the camera at `time` between the surrounding keyframes.
+///
+/// The segment position is eased with a smoothstep curve `3t² - 2t³` so the
+/// camera accelerates out of and decelerates into each keyframe. Using the
+/// eased position `s`:
+///
+/// * the center is interpolated linearly, `c0 + (c1 - c0) * s`
+/// * the radius is interpolated geometrically, `r0 * (r1 / r0)^s`, so the
+///   zoom proceeds at a constant rate per frame instead of slowing down
+/// * the iteration depth is interpolated linearly and rounded
+/// * the palette shift is interpolated linearly
+///
+/// Times before the first or after the last keyframe are clamped to those.
+pub fn interpolate(keyframes: &[Keyframe], time: f32) -> Keyframe {
+    let first = keyframes[0];
+    let last = keyframes[keyframes.len() - 1];
+
+    if time <= first.time {
+        return first;
+    } else if time >= last.time {
+        return last;
+    }
+
+    let segment = keyframes.windows(2).find(|pair| time <= pair[1].time).unwrap_or(&keyframes[keyframes.len() - 2..]);
+    let (from, to) = (segment[0], segment[1]);
+
+    let s = ease_in_out((time - from.time) / (to.time - from.time));
+    let lerp = |a: f32, b: f32| a + (b - a) * s;
+
+    Keyframe {
+        time,
+        center: (lerp(from.center.0, to.center.0), lerp(from.center.1, to.center.1)),
+        radius: from.radius * (to.radius / from.radius).powf(s),
+        iterations: lerp(from.iterations as f32, to.iterations as f32).round() as u32,
+        palette_shift: lerp(from.palette_shift, to.palette_shift)
+    }
+}
+
+pub fn frame_count(keyframes: &[Keyframe], fps: f32) -> u32 {
+    let duration = keyframes[keyframes.len() - 1].time - keyframes[0].time;
+    (duration * fps).floor() as u32 + 1
+}
+
+pub fn frame_name(name: &str, index: u32) -> String {
+    util::suffixed_name(name, &format!("_{:05}", index))
+}
+
+/// Renders every frame of the animation described by `keyframes`, passing
+/// each finished frame along with its index to `on_frame`.
+pub fn render_animation<F>(params: &RenderParams, keyframes: &[Keyframe], fps: f32, mut on_frame: F) -> Result<(), MandelbrotError>
+    where F: FnMut(u32, image::RgbImage) -> Result<(), MandelbrotError>
+{
+    if !(fps.is_finite() && fps > 0.0) {
+        return Err(MandelbrotError::InvalidParameter(format!("fps must be a positive number, got {}", fps)));
+    }
+
+    for index in 0..frame_count(keyframes, fps) {
+        let keyframe = interpolate(keyframes, keyframes[0].time + index as f32 / fps);
+
+        let frame_params = RenderParams {
+            center: keyframe.center,
+            radius: (keyframe.radius, keyframe.radius * params.radius.1 / params.radius.0),
+            iterations: keyframe.iterations,
+            palette_shift: keyframe.palette_shift,
+            ..params.clone()
+        };
+
+        on_frame(index, render(&frame_params, |_| {})?)?;
+    }
+
+    Ok(())
+}
+
+/// Parses an iteration sweep given as `start,end,frames` into the iteration
+/// depth of every frame, spaced evenly from `start` to `end` inclusive.
+pub fn parse_sweep(input: &str) -> Result<Vec<u32>, MandelbrotError> {
+    let fields = input.split(',').map(|field| {
+        field.trim().parse::<u32>().map_err(|_| MandelbrotError::Parse(format!("'{}' in iteration sweep '{}'", field, input)))
+    }).collect::<Result<Vec<u32>, MandelbrotError>>()?;
+
+    let (start, end, frames) = match fields[..] {
+        [start, end, frames] => (start, end, frames),
+        _ => return Err(MandelbrotError::Parse(format!("'{}', expected an iteration sweep as start,end,frames", input)))
+    };
+
+    if start == 0 || end == 0 || frames == 0 {
+        return Err(MandelbrotError::InvalidParameter(
+            format!("iteration sweep needs positive depths and frame count, got {}", input)
+        ));
+    }
+
+    Ok(sweep_levels(start, end, frames))
+}
+
+pub fn sweep_levels(start: u32, end: u32, frames: u32) -> Vec<u32> {
+    if frames == 1 {
+        return vec![start];
+    }
+
+    (0..frames as u64).map(|i| {
+        let offset = (end as i64 - start as i64) * i as i64 / (frames as i64 - 1);
+        (start as i64 + offset) as u32
+    }).collect()
+}
+
+/// Renders the same view once per iteration depth in `levels`, passing each
+/// frame along with its index to `on_frame`.
+pub fn render_sweep<F>(params: &RenderParams, levels: &[u32], mut on_frame: F) -> Result<(), MandelbrotError>
+    where F: FnMut(u32, image::RgbImage) -> Result<(), MandelbrotError>
+{
+    for (index, &iterations) in levels.iter().enumerate() {
+        let frame_params = RenderParams { iterations, ..params.clone() };
+        on_frame(index as u32, render(&frame_params, |_| {})?)?;
+    }
+
+    Ok(())
+}
+
+/// Opens an animated GIF that loops forever, for `render_animation` frames
+/// to be encoded into. GIF frames hold at most 256 colors, which the encoder
+/// picks for each frame on its own, so smooth gradients band and flicker a
+/// little and files end up far larger than a video of the same clip. They
+/// are meant for quick, easily shared previews.
+pub fn gif_encoder(path: &str) -> Result<GifEncoder<BufWriter<File>>, MandelbrotError> {
+    let mut encoder = GifEncoder::new(BufWriter::new(File::create(path)?));
+    encoder.set_repeat(Repeat::Infinite)?;
+
+    Ok(encoder)
+}
+
+/// Appends a frame shown for `delay_ms` milliseconds. GIF stores delays in
+/// hundredths of a second, so the delay is rounded to that.
+pub fn encode_gif_frame<W: Write>(encoder: &mut GifEncoder<W>, imgbuf: image::RgbImage, delay_ms: u32) -> Result<(), MandelbrotError> {
+    let rgba = image::DynamicImage::ImageRgb8(imgbuf).into_rgba8();
+    let delay = image::Delay::from_numer_denom_ms(delay_ms, 1);
+
+    Ok(encoder.encode_frame(image::Frame::from_parts(rgba, 0, 0, delay))?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn midpoint_between_keyframes() {
+        let keyframes = parse_keyframes("# time re im radius iterations shift\n0 -0.75 0 4 64\n2 -0.25 0.5 0.25 256 0.5\n").unwrap();
+        let middle = interpolate(&keyframes, 1.0);
+
+        // The eased position is exactly one half in the middle of a segment.
+        assert_eq!(middle.center, (-0.5, 0.25));
+        assert!((middle.radius - 1.0).abs() < 1e-6, "radius {}", middle.radius);
+        assert_eq!(middle.iterations, 160);
+        assert_eq!(middle.palette_shift, 0.25);
+
+        assert_eq!(interpolate(&keyframes, -1.0), keyframes[0]);
+        assert_eq!(interpolate(&keyframes, 3.0), keyframes[1]);
+    }
+
+    #[test]
+    fn gif_holds_every_encoded_frame() {
+        use image::AnimationDecoder;
+
+        let path = std::env::temp_dir().join(format!("mandelbrot-animation-{}.gif", std::process::id()));
+        let path = path.to_str().unwrap();
+
+        {
+            let mut encoder = gif_encoder(path).unwrap();
+
+            for shade in [0, 128, 255] {
+                encode_gif_frame(&mut encoder, image::RgbImage::from_pixel(16, 12, image::Rgb([shade, shade, shade])), 100).unwrap();
+            }
+        }
+
+        let decoder = image::codecs::gif::GifDecoder::new(File::open(path).unwrap()).unwrap();
+        let frames = decoder.into_frames().collect_frames().unwrap();
+        std::fs::remove_file(path).unwrap();
+
+        assert_eq!(frames.len(), 3);
+        assert_eq!(frames[1].buffer().dimensions(), (16, 12));
+        assert_eq!(frames[1].buffer().get_pixel(0, 0), &image::Rgba([128, 128, 128, 255]));
+        assert_eq!(frames[2].delay().numer_denom_ms(), (100, 1));
+    }
+
+    #[test]
+    fn sweep_renders_one_frame_per_scheduled_depth() {
+        let levels = parse_sweep("10,100,4").unwrap();
+        assert_eq!(levels, [10, 40, 70, 100]);
+        assert_eq!(parse_sweep("64,64,1").unwrap(), [64]);
+        assert!(parse_sweep("0,100,4").is_err() && parse_sweep("10,100").is_err());
+
+        let params = crate::RenderBuilder::new().dimensions(32, 24).color(true).build().unwrap();
+        let mut indices = Vec::new();
+
+        render_sweep(&params, &levels, |index, imgbuf| {
+            let expected = RenderParams { iterations: levels[index as usize], ..params.clone() };
+            assert_eq!(imgbuf, render(&expected, |_| {})?, "frame {}", index);
+
+            indices.push(index);
+            Ok(())
+        }).unwrap();
+
+        assert_eq!(indices, [0, 1, 2, 3]);
+    }
+}
@@ -0,0 +1,158 @@
+use serde::{Deserialize, Serialize};
+use std::io::Write;
+
+use crate::error::MandelbrotError;
+use crate::render::RenderParams;
+
+/// A saved viewport.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Bookmark {
+    pub label: String,
+    pub center: (f32, f32),
+    pub radius: (f32, f32),
+    pub iterations: u32
+}
+
+impl Bookmark {
+    pub fn from_params(label: &str, params: &RenderParams) -> Bookmark {
+        Bookmark { label: label.to_string(), center: params.center, radius: params.radius, iterations: params.iterations }
+    }
+
+    pub fn apply(&self, params: &RenderParams) -> RenderParams {
+        RenderParams {
+            center: self.center,
+            radius: self.radius,
+            iterations: self.iterations,
+            ..params.clone()
+        }
+    }
+
+    /// The bookmark as a single line JSON object. Numbers are written in
+    /// their shortest form that parses back to the same `f32`, so a bookmark
+    /// restores the viewport exactly.
+    pub fn to_json(&self) -> String {
+        serde_json::to_string(self).expect("bookmarks with finite coordinates always serialize")
+    }
+}
+
+/// Parses a bookmark file, which holds one JSON object per line:
+///
+/// ```text
+/// {"label":"seahorse","center":[-0.75,0.1],"radius":[0.05,0.05],"iterations":256}
+/// ```
+///
+/// Blank lines are skipped and unknown keys ignored.
+pub fn parse_bookmarks(input: &str) -> Result<Vec<Bookmark>, MandelbrotError> {
+    let mut bookmarks = Vec::new();
+
+    for (number, line) in input.lines().enumerate() {
+        let line = line.trim();
+
+        if line.is_empty() {
+            continue;
+        }
+
+        let error = |msg: String| MandelbrotError::Parse(format!("bookmark on line {}, {}", number + 1, msg));
+        // Every line is parsed on its own, so serde's line number is always 1.
+        let bookmark: Bookmark = serde_json::from_str(line)
+            .map_err(|err| error(err.to_string().replace(" at line 1 column ", " at column ")))?;
+
+        if bookmark.iterations == 0 {
+            return Err(error("'iterations' must be a positive whole number".to_string()));
+        }
+
+        bookmarks.push(bookmark);
+    }
+
+    Ok(bookmarks)
+}
+
+/// Contents of a bookmark file, empty if it does not exist yet.
+fn read_file(path: &str) -> Result<String, MandelbrotError> {
+    match std::fs::read_to_string(path) {
+        Ok(input) => Ok(input),
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => Ok(String::new()),
+        Err(err) => Err(err.into())
+    }
+}
+
+pub fn read_bookmarks(path: &str) -> Result<Vec<Bookmark>, MandelbrotError> {
+    parse_bookmarks(&read_file(path)?)
+}
+
+pub fn find_bookmark(bookmarks: &[Bookmark], label: &str) -> Result<Bookmark, MandelbrotError> {
+    bookmarks.iter().find(|bookmark| bookmark.label == label).cloned().ok_or_else(|| {
+        MandelbrotError::InvalidParameter(format!("no bookmark labelled '{}'", label))
+    })
+}
+
+/// Appends a bookmark to a file. A bookmark with the same label is an error
+/// unless `replace` is set, in which case it is overwritten in place.
+pub fn save_bookmark(path: &str, bookmark: &Bookmark, replace: bool) -> Result<(), MandelbrotError> {
+    let values = [bookmark.center.0, bookmark.center.1, bookmark.radius.0, bookmark.radius.1];
+
+    if values.iter().any(|value| !value.is_finite()) {
+        return Err(MandelbrotError::InvalidParameter("cannot bookmark a viewport with non-finite coordinates".to_string()));
+    }
+
+    let input = read_file(path)?;
+    let mut bookmarks = parse_bookmarks(&input)?;
+
+    match bookmarks.iter_mut().find(|existing| existing.label == bookmark.label) {
+        Some(existing) if replace => *existing = bookmark.clone(),
+        Some(_) => return Err(MandelbrotError::InvalidParameter(
+            format!("a bookmark labelled '{}' already exists in '{}'", bookmark.label, path)
+        )),
+        None => {
+            let mut file = std::fs::OpenOptions::new().create(true).append(true).open(path)?;
+            let separator = if input.is_empty() || input.ends_with('\n') { "" } else { "\n" };
+            writeln!(file, "{}{}", separator, bookmark.to_json())?;
+
+            return Ok(());
+        }
+    }
+
+    let lines: Vec<String> = bookmarks.iter().map(|bookmark| bookmark.to_json() + "\n").collect();
+    std::fs::write(path, lines.concat())?;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn saved_bookmarks_restore_the_viewport_exactly() {
+        let path = std::env::temp_dir().join(format!("mandelbrot-bookmarks-{}.jsonl", std::process::id()));
+        let path = path.to_str().unwrap();
+        let _ = std::fs::remove_file(path);
+
+        let params = crate::RenderBuilder::new().center(-0.743_643_9, 0.131_825_9).radii(1.7e-5, 1.1e-5).iterations(2048).build().unwrap();
+        let seahorse = Bookmark::from_params("seahorse", &params);
+
+        save_bookmark(path, &seahorse, false).unwrap();
+        save_bookmark(path, &Bookmark { label: "home".to_string(), center: (-0.75, 0.0), radius: (3.0, 3.0), iterations: 64 }, false).unwrap();
+        assert!(matches!(save_bookmark(path, &seahorse, false), Err(MandelbrotError::InvalidParameter(_))));
+
+        let restored = find_bookmark(&read_bookmarks(path).unwrap(), "seahorse").unwrap().apply(&crate::RenderBuilder::new().build().unwrap());
+        assert_eq!((restored.center, restored.radius, restored.iterations), (params.center, params.radius, params.iterations));
+
+        let moved = Bookmark { center: (0.25, 0.0), ..seahorse.clone() };
+        save_bookmark(path, &moved, true).unwrap();
+        let bookmarks = read_bookmarks(path).unwrap();
+        std::fs::remove_file(path).unwrap();
+
+        assert_eq!(bookmarks.iter().map(|bookmark| bookmark.label.as_str()).collect::<Vec<_>>(), ["seahorse", "home"]);
+        assert_eq!(bookmarks[0], moved);
+    }
+
+    #[test]
+    fn malformed_lines_name_their_line_number() {
+        let error = parse_bookmarks("{\"label\":\"a\",\"center\":[0,0],\"radius\":[1,1],\"iterations\":8}\n\n{\"label\":\"b\"}\n").unwrap_err();
+        assert!(error.to_string().contains("bookmark on line 3"), "{}", error);
+
+        let error = parse_bookmarks("{\"label\":\"a\",\"center\":[0,0],\"radius\":[1,1],\"iterations\":0}").unwrap_err();
+        assert!(error.to_string().contains("'iterations' must be a positive whole number"), "{}", error);
+    }
+}
@@ -0,0 +1,315 @@
+use num::complex::Complex;
+use std::sync::Arc;
+
+use crate::error::MandelbrotError;
+use crate::mandelbrot::{BailoutShape, Fractal, IndexChannel, InteriorMode, SinePalette, Tonemap};
+use crate::render::{Mapping, RenderParams, Schedule, TileOrder};
+use crate::util::SamplePattern;
+
+/// Builds `RenderParams` from the same defaults as the command line tool,
+/// e.g.
+///
+/// ```
+/// let params = mandelbrot::RenderBuilder::new()
+///     .center(-0.75, 0.0)
+///     .radius(3.0)
+///     .dimensions(640, 480)
+///     .iterations(256)
+///     .color(true)
+///     .build()?;
+/// # Ok::<(), mandelbrot::MandelbrotError>(())
+/// ```
+///
+/// Anything without a setter can still be changed on the built parameters.
+#[derive(Clone, Debug)]
+pub struct RenderBuilder {
+    params: RenderParams
+}
+
+impl Default for RenderBuilder {
+    fn default() -> Self {
+        RenderBuilder::new()
+    }
+}
+
+impl RenderBuilder {
+    pub fn new() -> RenderBuilder {
+        RenderBuilder {
+            params: RenderParams {
+                width: 1000,
+                height: 1000,
+                center: (-0.75, 0.3),
+                radius: (0.5, 0.5),
+                mapping: Mapping::Linear,
+                fractal: Fractal::Mandelbrot,
+                iterations: 32,
+                iteration_mask: None,
+                newton_iterations: None,
+                convergence_eps: 1e-3,
+                julia: Complex::new(-0.8, 0.156),
+                bailout: 2.0,
+                bailout_shape: BailoutShape::Circle,
+                power: 2,
+                threads: 10,
+                queue_depth: None,
+                tile_size: 64,
+                schedule: Schedule::Dynamic,
+                tile_order: TileOrder::RowMajor,
+                samples: 1,
+                sample_pattern: SamplePattern::Grid,
+                sample_offset: 0.5,
+                color: false,
+                sine_palette: None,
+                gradient: None,
+                smooth: false,
+                smooth_strength: 1.0,
+                potential: false,
+                equipotential_bands: 0,
+                palette_shift: 0.0,
+                color_cycles: 1,
+                index_channel: IndexChannel::Iteration,
+                tonemap: Tonemap::Linear,
+                exposure: 1.0,
+                interior: InteriorMode::Solid,
+                fast_paths: true,
+                symmetry: true,
+                prior: None,
+                cancel: None,
+                stats: None
+            }
+        }
+    }
+
+    pub fn center(mut self, re: f32, im: f32) -> Self {
+        self.params.center = (re, im);
+        self
+    }
+
+    /// Sets the extent of the view along both axes.
+    pub fn radius(mut self, radius: f32) -> Self {
+        self.params.radius = (radius, radius);
+        self
+    }
+
+    /// Sets the extent of the view along the real and imaginary axis.
+    pub fn radii(mut self, radius_x: f32, radius_y: f32) -> Self {
+        self.params.radius = (radius_x, radius_y);
+        self
+    }
+
+    pub fn dimensions(mut self, width: u32, height: u32) -> Self {
+        self.params.width = width;
+        self.params.height = height;
+        self
+    }
+
+    pub fn iterations(mut self, iterations: u32) -> Self {
+        self.params.iterations = iterations;
+        self
+    }
+
+    pub fn fractal(mut self, fractal: Fractal) -> Self {
+        self.params.fractal = fractal;
+        self
+    }
+
+    pub fn julia(mut self, re: f32, im: f32) -> Self {
+        self.params.julia = Complex::new(re, im);
+        self
+    }
+
+    pub fn bailout(mut self, bailout: f32) -> Self {
+        self.params.bailout = bailout;
+        self
+    }
+
+    pub fn bailout_shape(mut self, bailout_shape: BailoutShape) -> Self {
+        self.params.bailout_shape = bailout_shape;
+        self
+    }
+
+    pub fn power(mut self, power: u32) -> Self {
+        self.params.power = power;
+        self
+    }
+
+    pub fn newton_iterations(mut self, newton_iterations: Option<u32>) -> Self {
+        self.params.newton_iterations = newton_iterations;
+        self
+    }
+
+    pub fn convergence_eps(mut self, convergence_eps: f32) -> Self {
+        self.params.convergence_eps = convergence_eps;
+        self
+    }
+
+    pub fn mapping(mut self, mapping: Mapping) -> Self {
+        self.params.mapping = mapping;
+        self
+    }
+
+    pub fn threads(mut self, threads: usize) -> Self {
+        self.params.threads = threads;
+        self
+    }
+
+    pub fn queue_depth(mut self, queue_depth: Option<usize>) -> Self {
+        self.params.queue_depth = queue_depth;
+        self
+    }
+
+    pub fn tile_size(mut self, tile_size: u32) -> Self {
+        self.params.tile_size = tile_size;
+        self
+    }
+
+    pub fn schedule(mut self, schedule: Schedule) -> Self {
+        self.params.schedule = schedule;
+        self
+    }
+
+    pub fn tile_order(mut self, tile_order: TileOrder) -> Self {
+        self.params.tile_order = tile_order;
+        self
+    }
+
+    pub fn samples(mut self, samples: u32) -> Self {
+        self.params.samples = samples;
+        self
+    }
+
+    pub fn sample_pattern(mut self, sample_pattern: SamplePattern) -> Self {
+        self.params.sample_pattern = sample_pattern;
+        self
+    }
+
+    pub fn sample_offset(mut self, sample_offset: f32) -> Self {
+        self.params.sample_offset = sample_offset;
+        self
+    }
+
+    pub fn color(mut self, color: bool) -> Self {
+        self.params.color = color;
+        self
+    }
+
+    pub fn sine_palette(mut self, sine_palette: Option<SinePalette>) -> Self {
+        self.params.sine_palette = sine_palette;
+        self
+    }
+
+    pub fn gradient(mut self, gradient: Option<Arc<Vec<image::Rgb<u8>>>>) -> Self {
+        self.params.gradient = gradient;
+        self
+    }
+
+    pub fn smooth(mut self, smooth: bool) -> Self {
+        self.params.smooth = smooth;
+        self
+    }
+
+    pub fn smooth_strength(mut self, smooth_strength: f32) -> Self {
+        self.params.smooth_strength = smooth_strength;
+        self
+    }
+
+    pub fn potential(mut self, potential: bool) -> Self {
+        self.params.potential = potential;
+        self
+    }
+
+    pub fn equipotential_bands(mut self, equipotential_bands: u32) -> Self {
+        self.params.equipotential_bands = equipotential_bands;
+        self
+    }
+
+    pub fn palette_shift(mut self, palette_shift: f32) -> Self {
+        self.params.palette_shift = palette_shift;
+        self
+    }
+
+    pub fn color_cycles(mut self, color_cycles: u32) -> Self {
+        self.params.color_cycles = color_cycles;
+        self
+    }
+
+    pub fn index_channel(mut self, index_channel: IndexChannel) -> Self {
+        self.params.index_channel = index_channel;
+        self
+    }
+
+    pub fn tonemap(mut self, tonemap: Tonemap) -> Self {
+        self.params.tonemap = tonemap;
+        self
+    }
+
+    pub fn exposure(mut self, exposure: f32) -> Self {
+        self.params.exposure = exposure;
+        self
+    }
+
+    pub fn interior(mut self, interior: InteriorMode) -> Self {
+        self.params.interior = interior;
+        self
+    }
+
+    /// Allows the interior and periodicity shortcuts where they leave the
+    /// output unchanged.
+    pub fn fast_paths(mut self, fast_paths: bool) -> Self {
+        self.params.fast_paths = fast_paths;
+        self
+    }
+
+    /// Allows mirroring centered Julia set views instead of computing both
+    /// halves.
+    pub fn symmetry(mut self, symmetry: bool) -> Self {
+        self.params.symmetry = symmetry;
+        self
+    }
+
+    /// Validates and returns the parameters.
+    pub fn build(self) -> Result<RenderParams, MandelbrotError> {
+        self.params.validate()?;
+        Ok(self.params)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn defaults_render_the_default_view() {
+        let params = RenderBuilder::new().build().unwrap();
+
+        assert_eq!((params.width, params.height), (1000, 1000));
+        assert_eq!((params.center, params.radius), ((-0.75, 0.3), (0.5, 0.5)));
+        assert_eq!((params.iterations, params.samples, params.sample_offset), (32, 1, 0.5));
+        assert_eq!((params.fractal, params.bailout, params.power), (Fractal::Mandelbrot, 2.0, 2));
+        assert_eq!((params.threads, params.tile_size), (10, 64));
+        assert!(!params.color && !params.smooth && params.fast_paths);
+    }
+
+    #[test]
+    fn setters_override_the_defaults() {
+        let params = RenderBuilder::new().center(0.25, -0.5).radii(2.0, 1.0).dimensions(64, 32).iterations(500)
+            .samples(4).color(true).smooth(true).threads(3).build().unwrap();
+
+        assert_eq!((params.center, params.radius, params.width, params.height), ((0.25, -0.5), (2.0, 1.0), 64, 32));
+        assert_eq!((params.iterations, params.samples, params.threads), (500, 4, 3));
+        assert!(params.color && params.smooth);
+    }
+
+    #[test]
+    fn invalid_parameters_are_rejected() {
+        for builder in [
+            RenderBuilder::new().dimensions(0, 100),
+            RenderBuilder::new().dimensions(100, 0),
+            RenderBuilder::new().samples(0),
+            RenderBuilder::new().threads(0),
+            RenderBuilder::new().sample_offset(1.0)
+        ] {
+            assert!(matches!(builder.build(), Err(MandelbrotError::InvalidParameter(_))));
+        }
+    }
+}
@@ -0,0 +1,81 @@
+//! C ABI for driving the renderer from other languages. Built with the
+//! `capi` feature, and into a shared library holding the symbols with
+//!
+//! ```text
+//! cargo rustc --lib --release --features capi --crate-type cdylib
+//! ```
+
+use crate::builder::RenderBuilder;
+use crate::render::render_into;
+
+/// Status returned when the buffer was filled.
+pub const MANDELBROT_OK: i32 = 0;
+/// Status returned when `out_ptr` is null.
+pub const MANDELBROT_NULL_POINTER: i32 = 1;
+/// Status returned when the render panicked.
+pub const MANDELBROT_PANIC: i32 = 6;
+
+/// Renders a Mandelbrot set view into a caller allocated RGBA buffer, using
+/// the same defaults as the command line tool for everything not passed in.
+///
+/// Returns `MANDELBROT_OK`, `MANDELBROT_NULL_POINTER`, `MANDELBROT_PANIC` or
+/// the exit code of the command line tool for the same error, e.g. 2 if
+/// `out_len` does not match the dimensions.
+///
+/// # Safety
+///
+/// `out_ptr` must point to `out_len` writable bytes, which should be
+/// `width * height * 4`. The buffer stays owned by the caller and is not
+/// retained after the call returns; on any status but `MANDELBROT_OK` its
+/// contents are unspecified.
+#[no_mangle]
+pub unsafe extern "C" fn mandelbrot_render(
+    center_re: f32, center_im: f32, radius: f32,
+    width: u32, height: u32, iterations: u32, color: bool,
+    out_ptr: *mut u8, out_len: usize
+) -> i32 {
+    if out_ptr.is_null() {
+        return MANDELBROT_NULL_POINTER;
+    }
+
+    let buffer = std::slice::from_raw_parts_mut(out_ptr, out_len);
+
+    let params = match RenderBuilder::new()
+        .center(center_re, center_im)
+        .radius(radius)
+        .dimensions(width, height)
+        .iterations(iterations)
+        .color(color)
+        .build() {
+        Ok(params) => params,
+        Err(err) => return err.exit_code()
+    };
+
+    // Unwinding into foreign code is undefined behaviour. The buffer is left
+    // unspecified after a panic, so observing it half written is fine.
+    match std::panic::catch_unwind(std::panic::AssertUnwindSafe(move|| render_into(buffer, &params))) {
+        Ok(Ok(())) => MANDELBROT_OK,
+        Ok(Err(err)) => err.exit_code(),
+        Err(_) => MANDELBROT_PANIC
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn renders_into_the_callers_buffer() {
+        let mut buffer = vec![0; 40 * 30 * 4];
+        let status = unsafe { mandelbrot_render(-0.75, 0.0, 3.0, 40, 30, 64, true, buffer.as_mut_ptr(), buffer.len()) };
+        assert_eq!(status, MANDELBROT_OK);
+
+        let params = RenderBuilder::new().center(-0.75, 0.0).radius(3.0).dimensions(40, 30).iterations(64).color(true).build().unwrap();
+        let mut expected = vec![0; buffer.len()];
+        render_into(&mut expected, &params).unwrap();
+        assert_eq!(buffer, expected);
+
+        assert_eq!(unsafe { mandelbrot_render(-0.75, 0.0, 3.0, 40, 30, 64, true, buffer.as_mut_ptr(), 12) }, 2);
+        assert_eq!(unsafe { mandelbrot_render(-0.75, 0.0, 3.0, 40, 30, 64, true, std::ptr::null_mut(), 0) }, MANDELBROT_NULL_POINTER);
+    }
+}
@@ -0,0 +1,67 @@
+use crate::error::MandelbrotError;
+
+/// Summary of the per-channel differences between two images.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct DiffStats {
+    pub max_delta: u8,
+    pub mean_delta: f64,
+    pub differing_pixels: u64
+}
+
+/// Compares two images of the same size channel by channel. The returned
+/// image holds the absolute difference of every channel, multiplied by
+/// `amplify` so that small deviations become visible.
+pub fn compare_images(a: &image::RgbImage, b: &image::RgbImage, amplify: f32) -> Result<(image::RgbImage, DiffStats), MandelbrotError> {
+    if a.dimensions() != b.dimensions() {
+        return Err(MandelbrotError::InvalidParameter(format!(
+            "cannot compare a {}x{} image with a {}x{} one",
+            a.width(), a.height(), b.width(), b.height()
+        )));
+    }
+
+    let mut diff = image::ImageBuffer::new(a.width(), a.height());
+    let mut max_delta = 0;
+    let mut total: u64 = 0;
+    let mut differing_pixels = 0;
+
+    for (x, y, pixel) in diff.enumerate_pixels_mut() {
+        let delta = [0, 1, 2].map(|i| a.get_pixel(x, y)[i].abs_diff(b.get_pixel(x, y)[i]));
+
+        if delta != [0, 0, 0] {
+            differing_pixels += 1;
+        }
+
+        for channel in delta {
+            max_delta = max_delta.max(channel);
+            total += channel as u64;
+        }
+
+        *pixel = image::Rgb(delta.map(|channel| (channel as f32 * amplify).min(255.0) as u8));
+    }
+
+    let channels = a.width() as u64 * a.height() as u64 * 3;
+    let mean_delta = if channels == 0 { 0.0 } else { total as f64 / channels as f64 };
+
+    Ok((diff, DiffStats { max_delta, mean_delta, differing_pixels }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn comparing_with_itself_and_a_shifted_copy() {
+        let params = crate::RenderBuilder::new().dimensions(40, 30).iterations(64).color(true).build().unwrap();
+        let imgbuf = crate::render::render(&params, |_| {}).unwrap();
+
+        let (diff, stats) = compare_images(&imgbuf, &imgbuf, 8.0).unwrap();
+        assert_eq!(stats, DiffStats { max_delta: 0, mean_delta: 0.0, differing_pixels: 0 });
+        assert!(diff.pixels().all(|pixel| *pixel == image::Rgb([0, 0, 0])));
+
+        let shifted = image::ImageBuffer::from_fn(40, 30, |x, y| *imgbuf.get_pixel((x + 1) % 40, y));
+        let (_, stats) = compare_images(&imgbuf, &shifted, 8.0).unwrap();
+        assert!(stats.max_delta > 0 && stats.mean_delta > 0.0 && stats.differing_pixels > 0, "{:?}", stats);
+
+        assert!(compare_images(&imgbuf, &image::RgbImage::new(30, 40), 1.0).is_err());
+    }
+}
@@ -0,0 +1,53 @@
+use crate::render::EscapeGrid;
+
+/// Magnitude of the Sobel gradient of the escape counts at a pixel, with the
+/// counts normalized by the iteration depth. Neighbours outside the image
+/// are clamped to the nearest edge pixel.
+pub fn sobel_magnitude(grid: &EscapeGrid, iterations: u32, x: u32, y: u32) -> f32 {
+    let value = |dx: i64, dy: i64| {
+        let nx = (x as i64 + dx).clamp(0, grid.width as i64 - 1) as u32;
+        let ny = (y as i64 + dy).clamp(0, grid.height as i64 - 1) as u32;
+
+        grid.mean_iterations(nx, ny) / iterations.max(1) as f32
+    };
+
+    let gx = (value(1, -1) + 2.0 * value(1, 0) + value(1, 1))
+        - (value(-1, -1) + 2.0 * value(-1, 0) + value(-1, 1));
+    let gy = (value(-1, 1) + 2.0 * value(0, 1) + value(1, 1))
+        - (value(-1, -1) + 2.0 * value(0, -1) + value(1, -1));
+
+    (gx * gx + gy * gy).sqrt()
+}
+
+/// Draws the pixels whose gradient exceeds `threshold` in black on a white
+/// background, leaving only the boundary filaments of the set.
+pub fn render_edges(grid: &EscapeGrid, iterations: u32, threshold: f32) -> image::RgbImage {
+    image::ImageBuffer::from_fn(grid.width, grid.height, |x, y| {
+        if sobel_magnitude(grid, iterations, x, y) > threshold {
+            image::Rgb([0, 0, 0])
+        } else {
+            image::Rgb([255, 255, 255])
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn only_the_boundary_has_edges() {
+        let white = image::Rgb([255, 255, 255]);
+        let edges = |builder: crate::RenderBuilder| {
+            let params = builder.dimensions(48, 48).iterations(64).build().unwrap();
+            render_edges(&crate::render::compute_grid(&params, |_| {}).unwrap(), params.iterations, 0.1)
+        };
+
+        let interior = edges(crate::RenderBuilder::new().center(-0.1, 0.0).radius(0.05));
+        assert!(interior.pixels().all(|pixel| *pixel == white));
+
+        let boundary = edges(crate::RenderBuilder::new().center(-0.75, 0.0).radius(3.0));
+        assert!(boundary.pixels().any(|pixel| *pixel != white));
+        assert_eq!(*boundary.get_pixel(0, 0), white);
+    }
+}
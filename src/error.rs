@@ -0,0 +1,130 @@
+use std::fmt;
+
+#[derive(Debug)]
+pub enum MandelbrotError {
+    Parse(String),
+    Io(std::io::Error),
+    Image(image::ImageError),
+    InvalidParameter(String),
+    Render(String),
+    Cancelled(String),
+    Interrupted
+}
+
+impl MandelbrotError {
+    pub fn exit_code(&self) -> i32 {
+        match self {
+            MandelbrotError::Parse(_) | MandelbrotError::InvalidParameter(_) => 2,
+            MandelbrotError::Io(_) | MandelbrotError::Image(_) => 3,
+            MandelbrotError::Render(_) => 4,
+            MandelbrotError::Cancelled(_) => 5,
+            MandelbrotError::Interrupted => crate::interrupt::INTERRUPTED_EXIT_CODE
+        }
+    }
+}
+
+impl fmt::Display for MandelbrotError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            MandelbrotError::Parse(msg) => write!(f, "could not parse {}", msg),
+            MandelbrotError::Io(err) => write!(f, "I/O error: {}", err),
+            MandelbrotError::Image(err) => write!(f, "image error: {}", err),
+            MandelbrotError::InvalidParameter(msg) => write!(f, "invalid parameter: {}", msg),
+            MandelbrotError::Render(msg) => write!(f, "render failed: {}", msg),
+            MandelbrotError::Cancelled(msg) => write!(f, "render cancelled: {}", msg),
+            MandelbrotError::Interrupted => write!(f, "interrupted")
+        }
+    }
+}
+
+impl std::error::Error for MandelbrotError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            MandelbrotError::Io(err) => Some(err),
+            MandelbrotError::Image(err) => Some(err),
+            _ => None
+        }
+    }
+}
+
+impl From<std::io::Error> for MandelbrotError {
+    fn from(err: std::io::Error) -> Self {
+        MandelbrotError::Io(err)
+    }
+}
+
+impl From<image::ImageError> for MandelbrotError {
+    fn from(err: image::ImageError) -> Self {
+        MandelbrotError::Image(err)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::error::Error;
+    use std::sync::atomic::AtomicBool;
+    use std::sync::Arc;
+    use crate::builder::RenderBuilder;
+
+    #[test]
+    fn parse_errors() {
+        let err = crate::util::parse_tuple::<f32>("1;2", ",").unwrap_err();
+
+        assert!(matches!(err, MandelbrotError::Parse(_)));
+        assert_eq!(err.exit_code(), 2);
+        assert_eq!(err.to_string(), "could not parse '1;2', expected two values separated by ','");
+    }
+
+    #[test]
+    fn io_errors() {
+        let err = crate::gridfile::read_grid("does/not/exist.grid").unwrap_err();
+
+        assert!(matches!(err, MandelbrotError::Io(_)));
+        assert_eq!(err.exit_code(), 3);
+        assert!(err.source().is_some());
+    }
+
+    #[test]
+    fn image_errors() {
+        let err = crate::util::load_iteration_mask("Cargo.toml", 4, 4, 32).unwrap_err();
+
+        assert!(matches!(err, MandelbrotError::Image(_)));
+        assert_eq!(err.exit_code(), 3);
+        assert!(err.source().is_some());
+    }
+
+    #[test]
+    fn invalid_parameter_errors() {
+        let err = RenderBuilder::new().dimensions(0, 10).build().unwrap_err();
+
+        assert!(matches!(err, MandelbrotError::InvalidParameter(_)));
+        assert_eq!(err.exit_code(), 2);
+        assert!(err.source().is_none());
+    }
+
+    #[test]
+    fn render_errors() {
+        let err = MandelbrotError::Render("only 3 of 4 tiles were computed".to_string());
+
+        assert_eq!(err.exit_code(), 4);
+        assert_eq!(err.to_string(), "render failed: only 3 of 4 tiles were computed");
+    }
+
+    #[test]
+    fn cancelled_errors() {
+        let mut params = RenderBuilder::new().dimensions(32, 32).build().unwrap();
+        params.cancel = Some(Arc::new(AtomicBool::new(true)));
+
+        let err = crate::render(&params, |_| {}).unwrap_err();
+
+        assert!(matches!(err, MandelbrotError::Cancelled(_)));
+        assert_eq!(err.exit_code(), 5);
+    }
+
+    #[test]
+    fn interrupted_errors() {
+        assert_eq!(MandelbrotError::Interrupted.exit_code(), 130);
+        assert_eq!(MandelbrotError::Interrupted.to_string(), "interrupted");
+    }
+}
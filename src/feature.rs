@@ -0,0 +1,91 @@
+use crate::render::EscapeGrid;
+
+/// A square window of pixels and the variance of the escape counts in it.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Feature {
+    pub x: u32,
+    pub y: u32,
+    pub size: u32,
+    pub variance: f32
+}
+
+/// Scans windows of `size` pixels, overlapping by half their size, for the
+/// one whose mean escape counts vary the most. High variance means a lot of
+/// boundary detail, so this is a decent guess at where to zoom in next.
+/// Returns `None` if the window does not fit into the grid.
+pub fn find_feature(grid: &EscapeGrid, size: u32) -> Option<Feature> {
+    if size == 0 || size > grid.width || size > grid.height {
+        return None;
+    }
+
+    let step = (size / 2).max(1);
+    let offsets = |extent: u32| {
+        let mut offsets: Vec<u32> = (0..=extent - size).step_by(step as usize).collect();
+
+        // Make sure the window flush with the far edge is considered too.
+        if offsets.last() != Some(&(extent - size)) {
+            offsets.push(extent - size);
+        }
+
+        offsets
+    };
+
+    let values: Vec<f32> = (0..grid.height).flat_map(|y| {
+        (0..grid.width).map(move|x| grid.mean_iterations(x, y))
+    }).collect();
+
+    let mut best: Option<Feature> = None;
+
+    for y in offsets(grid.height) {
+        for x in offsets(grid.width) {
+            let (mut sum, mut sum_squared) = (0.0f64, 0.0f64);
+
+            for row in y..y + size {
+                for &value in &values[(row * grid.width + x) as usize..(row * grid.width + x + size) as usize] {
+                    sum += value as f64;
+                    sum_squared += value as f64 * value as f64;
+                }
+            }
+
+            let count = (size * size) as f64;
+            let variance = (sum_squared / count - (sum / count).powi(2)).max(0.0) as f32;
+
+            let better = match best {
+                Some(best) => variance > best.variance,
+                None => true
+            };
+
+            if better {
+                best = Some(Feature { x, y, size, variance });
+            }
+        }
+    }
+
+    best
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn finds_the_window_with_the_most_varied_counts() {
+        let mut grid = EscapeGrid { width: 32, height: 32, samples: 1, ..EscapeGrid::default() };
+
+        // Flat everywhere but for a checkerboard patch at (16, 8).
+        for y in 0..32 {
+            for x in 0..32 {
+                let patch = (16..24).contains(&x) && (8..16).contains(&y);
+                let iterations = if patch && (x + y) % 2 == 0 { 100 } else { 10 };
+
+                grid.push(crate::mandelbrot::Escape { in_set: false, iterations, z: num::complex::Complex::new(0.0, 0.0), channel: 0.0 });
+            }
+        }
+
+        let feature = find_feature(&grid, 8).unwrap();
+
+        assert_eq!((feature.x, feature.y, feature.size), (16, 8, 8));
+        assert!((feature.variance - 45.0 * 45.0).abs() < 1.0, "variance {}", feature.variance);
+        assert_eq!(find_feature(&grid, 33), None);
+    }
+}
@@ -0,0 +1,95 @@
+const EDGE_THRESHOLD_MIN: f32 = 1.0 / 16.0;
+const EDGE_THRESHOLD_RELATIVE: f32 = 1.0 / 8.0;
+const SUBPIXEL_QUALITY: f32 = 0.75;
+
+fn luma(pixel: &image::Rgb<u8>) -> f32 {
+    let [r, g, b] = pixel.0;
+    (0.299 * r as f32 + 0.587 * g as f32 + 0.114 * b as f32) / 255.0
+}
+
+/// A simplified FXAA pass over the finished image. Pixels whose local luma
+/// contrast is low are left untouched, others are blended with the
+/// neighbour across the detected edge. This softens stair-stepping without
+/// computing any extra samples, at the cost of some sharpness.
+pub fn post_antialias(imgbuf: &image::RgbImage) -> image::RgbImage {
+    let (width, height) = imgbuf.dimensions();
+
+    let at = |x: i64, y: i64| {
+        imgbuf.get_pixel(x.clamp(0, width as i64 - 1) as u32, y.clamp(0, height as i64 - 1) as u32)
+    };
+
+    image::ImageBuffer::from_fn(width, height, |x, y| {
+        let (x, y) = (x as i64, y as i64);
+        let center = at(x, y);
+
+        let (north, south) = (at(x, y - 1), at(x, y + 1));
+        let (west, east) = (at(x - 1, y), at(x + 1, y));
+
+        let luma_center = luma(center);
+        let (luma_north, luma_south, luma_west, luma_east) = (luma(north), luma(south), luma(west), luma(east));
+
+        let luma_max = luma_center.max(luma_north).max(luma_south).max(luma_west).max(luma_east);
+        let luma_min = luma_center.min(luma_north).min(luma_south).min(luma_west).min(luma_east);
+        let range = luma_max - luma_min;
+
+        if range < EDGE_THRESHOLD_MIN.max(luma_max * EDGE_THRESHOLD_RELATIVE) {
+            return *center;
+        }
+
+        let horizontal = (luma_north + luma_south - 2.0 * luma_center).abs()
+            >= (luma_west + luma_east - 2.0 * luma_center).abs();
+
+        let neighbour = if horizontal {
+            if (luma_north - luma_center).abs() >= (luma_south - luma_center).abs() { north } else { south }
+        } else if (luma_west - luma_center).abs() >= (luma_east - luma_center).abs() {
+            west
+        } else {
+            east
+        };
+
+        let average = (luma_north + luma_south + luma_west + luma_east) / 4.0;
+        let subpixel = ((average - luma_center).abs() / range).clamp(0.0, 1.0);
+        let blend = subpixel * subpixel * SUBPIXEL_QUALITY * 0.5;
+
+        image::Rgb([0, 1, 2].map(|i| {
+            (center.0[i] as f32 * (1.0 - blend) + neighbour.0[i] as f32 * blend).round() as u8
+        }))
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Sum of squared luma differences between horizontal and vertical
+    /// neighbours whose left or upper pixel lies in `columns`.
+    fn edge_energy(imgbuf: &image::RgbImage, columns: std::ops::Range<u32>) -> f32 {
+        let mut energy = 0.0;
+
+        for y in 0..imgbuf.height() - 1 {
+            for x in columns.clone() {
+                let center = luma(imgbuf.get_pixel(x, y));
+                energy += (center - luma(imgbuf.get_pixel(x + 1, y))).powi(2) + (center - luma(imgbuf.get_pixel(x, y + 1))).powi(2);
+            }
+        }
+
+        energy
+    }
+
+    #[test]
+    fn antialiasing_smooths_edges_and_keeps_flat_regions() {
+        // Flat grey on the left, a checkerboard on the right.
+        let imgbuf = image::ImageBuffer::from_fn(32, 16, |x, y| {
+            if x < 16 || (x + y) % 2 == 0 { image::Rgb([128, 128, 128]) } else { image::Rgb([255, 255, 255]) }
+        });
+        let smoothed = post_antialias(&imgbuf);
+
+        for y in 0..16 {
+            for x in 0..14 {
+                assert_eq!(smoothed.get_pixel(x, y), imgbuf.get_pixel(x, y));
+            }
+        }
+
+        assert!(edge_energy(&smoothed, 18..31) < edge_energy(&imgbuf, 18..31));
+    }
+}
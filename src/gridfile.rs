@@ -0,0 +1,314 @@
+use num::complex::Complex;
+use std::fs::File;
+use std::io::{BufReader, BufWriter, Read, Write};
+use std::sync::Arc;
+
+use crate::error::MandelbrotError;
+use crate::mandelbrot::{BailoutShape, Escape, Fractal};
+use crate::render::{EscapeGrid, Mapping, RenderParams};
+
+const MAGIC: &[u8; 8] = b"MBGRID3\0";
+
+/// Prefix shared by the magic numbers of every version of the format.
+const MAGIC_PREFIX: &[u8; 6] = b"MBGRID";
+
+/// The parameters that went into computing an exported grid. Everything
+/// needed to normalize and place the escape data is stored, the coloring
+/// options are left to whoever loads the grid.
+#[derive(Clone, Debug, PartialEq)]
+pub struct GridHeader {
+    pub width: u32,
+    pub height: u32,
+    pub samples: u32,
+    pub iterations: u32,
+    pub newton_iterations: Option<u32>,
+    pub fractal: Fractal,
+    pub bailout: f32,
+    pub bailout_shape: BailoutShape,
+    pub power: u32,
+    pub convergence_eps: f32,
+    pub julia: Complex<f32>,
+    pub center: (f32, f32),
+    pub radius: (f32, f32),
+    pub sample_offset: f32,
+    pub mapping: Mapping,
+    pub iteration_mask: Option<Arc<Vec<u32>>>
+}
+
+impl GridHeader {
+    pub fn from_params(grid: &EscapeGrid, params: &RenderParams) -> GridHeader {
+        GridHeader {
+            width: grid.width,
+            height: grid.height,
+            samples: grid.samples,
+            iterations: params.iterations,
+            newton_iterations: params.newton_iterations,
+            fractal: params.fractal,
+            bailout: params.bailout,
+            bailout_shape: params.bailout_shape,
+            power: params.power,
+            convergence_eps: params.convergence_eps,
+            julia: params.julia,
+            center: params.center,
+            radius: params.radius,
+            sample_offset: params.sample_offset,
+            mapping: params.mapping,
+            iteration_mask: params.iteration_mask.clone()
+        }
+    }
+
+    /// Replaces the geometry and iteration settings of `params` with the
+    /// ones the grid was computed with.
+    pub fn apply(&self, params: &RenderParams) -> RenderParams {
+        RenderParams {
+            width: self.width,
+            height: self.height,
+            samples: self.samples,
+            iterations: self.iterations,
+            newton_iterations: self.newton_iterations,
+            fractal: self.fractal,
+            bailout: self.bailout,
+            bailout_shape: self.bailout_shape,
+            power: self.power,
+            convergence_eps: self.convergence_eps,
+            julia: self.julia,
+            center: self.center,
+            radius: self.radius,
+            sample_offset: self.sample_offset,
+            mapping: self.mapping,
+            iteration_mask: self.iteration_mask.clone(),
+            ..params.clone()
+        }
+    }
+}
+
+fn fractal_id(fractal: Fractal) -> u8 {
+    match fractal {
+        Fractal::Mandelbrot => 0,
+        Fractal::Newton => 1,
+        Fractal::Julia => 2,
+        Fractal::BurningShip => 3,
+        Fractal::Tricorn => 4
+    }
+}
+
+fn bailout_shape_id(shape: BailoutShape) -> u8 {
+    match shape {
+        BailoutShape::Circle => 0,
+        BailoutShape::Square => 1,
+        BailoutShape::Diamond => 2
+    }
+}
+
+fn bailout_shape_from_id(id: u8) -> Result<BailoutShape, MandelbrotError> {
+    match id {
+        0 => Ok(BailoutShape::Circle),
+        1 => Ok(BailoutShape::Square),
+        2 => Ok(BailoutShape::Diamond),
+        _ => Err(MandelbrotError::Parse(format!("grid file, unknown bailout shape {}", id)))
+    }
+}
+
+fn fractal_from_id(id: u8) -> Result<Fractal, MandelbrotError> {
+    match id {
+        0 => Ok(Fractal::Mandelbrot),
+        1 => Ok(Fractal::Newton),
+        2 => Ok(Fractal::Julia),
+        3 => Ok(Fractal::BurningShip),
+        4 => Ok(Fractal::Tricorn),
+        _ => Err(MandelbrotError::Parse(format!("grid file, unknown fractal type {}", id)))
+    }
+}
+
+/// Writes the grid as a little endian binary file: a magic number, the
+/// header fields, the Newton iteration cap and the iteration mask if any,
+/// and then one record of in-set flag, iteration count and final `z` per
+/// sample.
+pub fn write_grid(path: &str, grid: &EscapeGrid, params: &RenderParams) -> Result<(), MandelbrotError> {
+    let header = GridHeader::from_params(grid, params);
+    let mut writer = BufWriter::new(File::create(path)?);
+
+    writer.write_all(MAGIC)?;
+
+    for value in [header.width, header.height, header.samples, header.iterations, header.power] {
+        writer.write_all(&value.to_le_bytes())?;
+    }
+
+    writer.write_all(&[fractal_id(header.fractal), bailout_shape_id(header.bailout_shape)])?;
+
+    for value in [
+        header.bailout, header.convergence_eps, header.julia.re, header.julia.im,
+        header.center.0, header.center.1, header.radius.0, header.radius.1, header.sample_offset
+    ] {
+        writer.write_all(&value.to_le_bytes())?;
+    }
+
+    let (mapping_id, log_radius) = match header.mapping {
+        Mapping::Linear => (0, (0.0, 0.0)),
+        Mapping::LogPolar { log_radius } => (1, log_radius)
+    };
+
+    writer.write_all(&[header.newton_iterations.is_some() as u8])?;
+    writer.write_all(&header.newton_iterations.unwrap_or(0).to_le_bytes())?;
+
+    writer.write_all(&[mapping_id])?;
+    writer.write_all(&log_radius.0.to_le_bytes())?;
+    writer.write_all(&log_radius.1.to_le_bytes())?;
+
+    writer.write_all(&[header.iteration_mask.is_some() as u8])?;
+
+    for value in header.iteration_mask.iter().flat_map(|mask| mask.iter()) {
+        writer.write_all(&value.to_le_bytes())?;
+    }
+
+    for escape in grid.escapes() {
+        writer.write_all(&[escape.in_set as u8])?;
+        writer.write_all(&escape.iterations.to_le_bytes())?;
+        writer.write_all(&escape.z.re.to_le_bytes())?;
+        writer.write_all(&escape.z.im.to_le_bytes())?;
+    }
+
+    writer.flush()?;
+    Ok(())
+}
+
+fn read_u32(reader: &mut impl Read) -> Result<u32, MandelbrotError> {
+    let mut bytes = [0; 4];
+    reader.read_exact(&mut bytes)?;
+    Ok(u32::from_le_bytes(bytes))
+}
+
+fn read_f32(reader: &mut impl Read) -> Result<f32, MandelbrotError> {
+    let mut bytes = [0; 4];
+    reader.read_exact(&mut bytes)?;
+    Ok(f32::from_le_bytes(bytes))
+}
+
+pub fn read_grid(path: &str) -> Result<(GridHeader, EscapeGrid), MandelbrotError> {
+    let mut reader = BufReader::new(File::open(path)?);
+
+    let mut magic = [0; 8];
+    reader.read_exact(&mut magic)?;
+
+    if &magic != MAGIC {
+        return Err(MandelbrotError::Parse(if magic.starts_with(MAGIC_PREFIX) {
+            format!("'{}', grid file of an older version, export it again", path)
+        } else {
+            format!("'{}', not an exported grid file", path)
+        }));
+    }
+
+    let (width, height, samples, iterations, power) = (
+        read_u32(&mut reader)?, read_u32(&mut reader)?, read_u32(&mut reader)?,
+        read_u32(&mut reader)?, read_u32(&mut reader)?
+    );
+
+    let mut ids = [0; 2];
+    reader.read_exact(&mut ids)?;
+
+    let mut header = GridHeader {
+        width, height, samples, iterations, power,
+        newton_iterations: None,
+        fractal: fractal_from_id(ids[0])?,
+        bailout: read_f32(&mut reader)?,
+        bailout_shape: bailout_shape_from_id(ids[1])?,
+        convergence_eps: read_f32(&mut reader)?,
+        julia: Complex::new(read_f32(&mut reader)?, read_f32(&mut reader)?),
+        center: (read_f32(&mut reader)?, read_f32(&mut reader)?),
+        radius: (read_f32(&mut reader)?, read_f32(&mut reader)?),
+        sample_offset: read_f32(&mut reader)?,
+        mapping: Mapping::Linear,
+        iteration_mask: None
+    };
+
+    if width == 0 || height == 0 || samples == 0 {
+        return Err(MandelbrotError::Parse(format!("'{}', grid has no data", path)));
+    }
+
+    let mut has_newton_iterations = [0; 1];
+    reader.read_exact(&mut has_newton_iterations)?;
+    let newton_iterations = read_u32(&mut reader)?;
+
+    if has_newton_iterations[0] != 0 {
+        header.newton_iterations = Some(newton_iterations);
+    }
+
+    let mut mapping_id = [0; 1];
+    reader.read_exact(&mut mapping_id)?;
+    let log_radius = (read_f32(&mut reader)?, read_f32(&mut reader)?);
+
+    header.mapping = match mapping_id[0] {
+        0 => Mapping::Linear,
+        1 => Mapping::LogPolar { log_radius },
+        id => return Err(MandelbrotError::Parse(format!("grid file, unknown mapping {}", id)))
+    };
+
+    let mut has_mask = [0; 1];
+    reader.read_exact(&mut has_mask)?;
+
+    if has_mask[0] != 0 {
+        let mask = (0..width as usize * height as usize).map(|_| read_u32(&mut reader)).collect::<Result<Vec<_>, _>>()?;
+        header.iteration_mask = Some(Arc::new(mask));
+    }
+
+    let count = width as usize * height as usize * samples as usize;
+    let mut grid = EscapeGrid { width, height, samples, ..EscapeGrid::default() };
+
+    for _ in 0..count {
+        let mut in_set = [0; 1];
+        reader.read_exact(&mut in_set)?;
+
+        grid.push(Escape {
+            in_set: in_set[0] != 0,
+            iterations: read_u32(&mut reader)?,
+            z: Complex::new(read_f32(&mut reader)?, read_f32(&mut reader)?),
+            channel: 0.0
+        });
+    }
+
+    Ok((header, grid))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn recoloring_an_exported_grid_matches_a_direct_render() {
+        let params = crate::RenderBuilder::new().dimensions(40, 30).iterations(64).samples(2).center(-0.5, 0.1).build().unwrap();
+        let path = std::env::temp_dir().join(format!("mandelbrot-gridfile-{}.grid", std::process::id()));
+        let path = path.to_str().unwrap();
+
+        write_grid(path, &crate::render::compute_grid(&params, |_| {}).unwrap(), &params).unwrap();
+        let (header, grid) = read_grid(path).unwrap();
+        std::fs::remove_file(path).unwrap();
+
+        // Only the coloring is taken from the params the grid is loaded with.
+        let recolor = crate::RenderBuilder::new().color(true).smooth(true).build().unwrap();
+        let recolor = header.apply(&recolor);
+
+        assert_eq!(header, GridHeader::from_params(&grid, &params));
+        assert_eq!(crate::render::colorize(&grid, &recolor), crate::render::render(&recolor, |_| {}).unwrap());
+    }
+
+    #[test]
+    fn header_keeps_the_mapping_mask_and_newton_cap() {
+        let path = std::env::temp_dir().join(format!("mandelbrot-gridfile-header-{}.grid", std::process::id()));
+        let path = path.to_str().unwrap();
+
+        let newton = crate::RenderBuilder::new().dimensions(20, 10).fractal(Fractal::Newton).iterations(8).newton_iterations(Some(60)).build().unwrap();
+        let log_polar = RenderParams { mapping: Mapping::LogPolar { log_radius: (-4.0, 1.0) }, ..newton.clone() };
+        let masked = RenderParams { iteration_mask: Some(Arc::new((0..200).collect())), ..newton.clone() };
+
+        for params in [newton, log_polar, masked] {
+            let grid = crate::render::compute_grid(&params, |_| {}).unwrap();
+            write_grid(path, &grid, &params).unwrap();
+            let (header, loaded) = read_grid(path).unwrap();
+
+            assert_eq!(header, GridHeader::from_params(&grid, &params));
+            assert_eq!(loaded.escapes().collect::<Vec<_>>(), grid.escapes().collect::<Vec<_>>());
+        }
+
+        std::fs::remove_file(path).unwrap();
+    }
+}
@@ -0,0 +1,76 @@
+use crate::render::EscapeGrid;
+
+/// Number of samples per escape count, from 0 up to and including the
+/// iteration depth, which is where points inside the set end up.
+pub fn escape_histogram(grid: &EscapeGrid, iterations: u32) -> Vec<u64> {
+    let mut counts = vec![0u64; iterations as usize + 1];
+
+    for &count in &grid.iterations {
+        counts[(count as usize).min(iterations as usize)] += 1;
+    }
+
+    counts
+}
+
+/// Plots the histogram as bars, iteration count along the horizontal axis
+/// and sample count (on a logarithmic scale) along the vertical one. The bar
+/// for points that hit the iteration depth is drawn in red, as a lot of mass
+/// there suggests increasing `--iterations`.
+pub fn draw_histogram(counts: &[u64], width: u32, height: u32) -> image::RgbImage {
+    let mut imgbuf = image::ImageBuffer::from_pixel(width, height, image::Rgb([255, 255, 255]));
+
+    let max = counts.iter().copied().max().unwrap_or(0);
+    if max == 0 || width == 0 || height == 0 {
+        return imgbuf;
+    }
+
+    let scale = (max as f32 + 1.0).ln();
+    let bins = counts.len() as u32;
+
+    for x in 0..width {
+        let bin = ((x as u64 * bins as u64) / width as u64) as usize;
+        let count = counts[bin];
+
+        if count == 0 {
+            continue;
+        }
+
+        let bar = (((count as f32 + 1.0).ln() / scale) * height as f32).round().max(1.0) as u32;
+        let color = if bin == counts.len() - 1 {
+            image::Rgb([200, 40, 40])
+        } else {
+            image::Rgb([40, 40, 40])
+        };
+
+        for y in height - bar.min(height)..height {
+            imgbuf.put_pixel(x, y, color);
+        }
+    }
+
+    imgbuf
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn histogram_has_the_requested_size_and_bars() {
+        let params = crate::RenderBuilder::new().dimensions(40, 30).iterations(64).build().unwrap();
+        let counts = escape_histogram(&crate::render::compute_grid(&params, |_| {}).unwrap(), params.iterations);
+
+        assert_eq!(counts.len(), 65);
+        assert_eq!(counts.iter().sum::<u64>(), 40 * 30);
+
+        let imgbuf = draw_histogram(&counts, 130, 50);
+        let white = image::Rgb([255, 255, 255]);
+
+        assert_eq!(imgbuf.dimensions(), (130, 50));
+        assert_eq!(*imgbuf.get_pixel(129, 49), image::Rgb([200, 40, 40]));
+
+        // Two columns per bin, each standing on the bottom row if the bin has samples.
+        for x in 0..130 {
+            assert_eq!(*imgbuf.get_pixel(x, 49) != white, counts[x as usize / 2] > 0, "column {}", x);
+        }
+    }
+}
@@ -0,0 +1,33 @@
+//! Ctrl-C handling. The first interrupt only raises a flag so that a render
+//! in progress can wind down and save what it has; a second one terminates
+//! the process as usual.
+
+use std::sync::atomic::{AtomicBool, Ordering};
+
+/// Exit code of a process that was interrupted, as shells report for SIGINT.
+pub const INTERRUPTED_EXIT_CODE: i32 = 130;
+
+static INTERRUPTED: AtomicBool = AtomicBool::new(false);
+
+#[cfg(unix)]
+extern "C" fn on_interrupt(_signal: libc::c_int) {
+    INTERRUPTED.store(true, Ordering::SeqCst);
+
+    // Only async-signal-safe calls are allowed in here, which signal is.
+    unsafe {
+        libc::signal(libc::SIGINT, libc::SIG_DFL);
+    }
+}
+
+/// Installs the handler. Does nothing on platforms without signals.
+pub fn install() {
+    #[cfg(unix)]
+    unsafe {
+        libc::signal(libc::SIGINT, on_interrupt as extern "C" fn(libc::c_int) as libc::sighandler_t);
+    }
+}
+
+/// Whether Ctrl-C was pressed since the handler was installed.
+pub fn interrupted() -> bool {
+    INTERRUPTED.load(Ordering::SeqCst)
+}
@@ -0,0 +1,28 @@
+pub mod animation;
+pub mod bookmarks;
+pub mod builder;
+#[cfg(feature = "capi")]
+pub mod capi;
+pub mod compare;
+pub mod edges;
+pub mod error;
+pub mod feature;
+pub mod filter;
+pub mod font;
+pub mod gridfile;
+pub mod histogram;
+pub mod interrupt;
+pub mod mandelbrot;
+pub mod montage;
+pub mod overlay;
+pub mod presets;
+pub mod progress;
+pub mod regions;
+pub mod render;
+pub mod sdf;
+pub mod selftest;
+pub mod util;
+
+pub use builder::RenderBuilder;
+pub use error::MandelbrotError;
+pub use render::{pixel_color, render, render_into, RenderParams};
@@ -1,106 +1,963 @@
-mod mandelbrot;
-mod util;
-
 use clap::Parser;
-use num::complex::Complex;
 use pbr::ProgressBar;
 use std::path::Path;
-use std::sync::mpsc::channel;
-use threadpool::ThreadPool;
+use std::sync::atomic::Ordering;
 use time::Instant;
 
-use mandelbrot::{get_mandelbrot_color, MandelbrotPoint};
+use mandelbrot::mandelbrot::{escape, BailoutShape, Fractal, IndexChannel, InteriorMode, SinePalette, Tonemap};
+use mandelbrot::overlay::{CaptionPosition, CaptionStyle};
+use mandelbrot::render::{colorize, compute_partial_grid, Mapping, PriorGrid, Region, RenderStats, Schedule, TileOrder};
+use mandelbrot::util::SamplePattern;
+use mandelbrot::{animation, bookmarks, compare, edges, feature, filter, gridfile, histogram, interrupt, montage, overlay, presets, progress, regions, render, sdf, selftest, util, MandelbrotError, RenderBuilder, RenderParams};
+
+const PREVIEW_SIZE: u32 = 256;
+const PREVIEW_ITERATIONS: u32 = 64;
+const WATCHDOG_INTERVAL: std::time::Duration = std::time::Duration::from_millis(50);
 
 #[derive(Parser, Debug)]
 struct Args {
     #[clap(short, long, help="Print verbose output")]
     verbose: bool,
 
+    #[clap(long, help="Write progress as JSON lines to this file descriptor number or path")]
+    progress_json: Option<String>,
+
     #[clap(long, help="Generate color image")]
     color: bool,
 
-    #[clap(short, long, default_value="-0.75,0.3", help="Center point of the set to examine")]
+    #[clap(long, requires="color", help="Color with one cosine per channel of the escape ratio, a rainbow unless --rgb-freqs or --rgb-phases say otherwise")]
+    sine_palette: bool,
+
+    #[clap(long, requires="color", help="Frequencies of the --sine-palette channel cosines as fr,fg,fb, turning the palette on [default: 1,1,1]")]
+    rgb_freqs: Option<String>,
+
+    #[clap(long, requires="color", conflicts_with_all=&["sine-palette", "rgb-freqs"], help="Color with a ramp of the colors in this image, from dark to bright")]
+    palette_from_image: Option<String>,
+
+    #[clap(long, default_value_t=16, requires="palette-from-image", help="Number of colors taken from the --palette-from-image image")]
+    palette_size: usize,
+
+    #[clap(long, default_value_t=SinePalette::RAINBOW.phases.map(|phase| phase.to_string()).join(","), help="Phases of the --sine-palette channel cosines as pr,pg,pb")]
+    rgb_phases: String,
+
+    #[clap(long, help="Use smooth (fractional) escape counts for coloring")]
+    smooth: bool,
+
+    #[clap(long, default_value_t=default_params().smooth_strength, help="Blend between integer bands (0) and fully smooth coloring (1) with --smooth")]
+    smooth_strength: f32,
+
+    #[clap(long, help="Color escaped points by their continuous potential log|z_n| / power^n, which needs a large --bailout such as 1000 to be free of steps")]
+    potential: bool,
+
+    #[clap(long, default_value_t=default_params().equipotential_bands, requires="potential", help="Quantize the potential into this many bands (0 keeps it continuous)")]
+    equipotential_bands: u32,
+
+    #[clap(long, default_value_t=default_params().palette_shift, help="Offset added to the palette position, wrapping around")]
+    palette_shift: f32,
+
+    #[clap(long, default_value_t=default_params().color_cycles, help="Repeat the palette this many times across the range of escape ratios")]
+    color_cycles: u32,
+
+    #[clap(long, value_enum, default_value_t=default_params().index_channel, help="Quantity of escaped points that picks their place in the palette")]
+    index_channel: IndexChannel,
+
+    #[clap(long, value_enum, default_value_t=default_params().tonemap, help="Curve compressing the escape ratios into the palette")]
+    tonemap: Tonemap,
+
+    #[clap(long, default_value_t=default_params().exposure, help="Factor the escape ratios are scaled by before tone mapping")]
+    exposure: f32,
+
+    #[clap(long, value_enum, default_value_t=default_params().interior, help="How to color points inside the set")]
+    interior_mode: InteriorMode,
+
+    #[clap(long, value_enum, default_value_t=default_params().fractal, help="The fractal to render")]
+    fractal: Fractal,
+
+    #[clap(long, default_value_t=default_pair(default_params().julia.re, default_params().julia.im, ","), help="Constant added in each step of a Julia set iteration")]
+    julia: String,
+
+    #[clap(long, default_value_t=default_params().convergence_eps, help="Distance to a root at which a Newton orbit counts as converged (too large blurs basin boundaries)")]
+    convergence_eps: f32,
+
+    #[clap(long, conflicts_with_all=&["refine", "recolor"], help="Separate iteration cap of the Newton fractal, --iterations if not given, which also scales its iteration count shading")]
+    newton_iterations: Option<u32>,
+
+    #[clap(short, long, default_value_t=default_pair(default_params().center.0, default_params().center.1, ","), help="Center point of the set to examine")]
     center: String,
 
-    #[clap(short, long, default_value="1000x1000", help="Dimensions of the output image")]
+    #[clap(short, long, default_value_t=default_pair(default_params().width, default_params().height, "x"), help="Dimensions of the output image")]
     dimensions: String,
 
-    #[clap(short, long, default_value_t=32, help="Iteration depth")]
+    #[clap(long, conflicts_with="dimensions", help="Pick dimensions for roughly this many megapixels instead of --dimensions")]
+    megapixels: Option<f32>,
+
+    #[clap(long, default_value="1:1", requires="megapixels", help="Aspect ratio as w:h used with --megapixels")]
+    aspect: String,
+
+    #[clap(short, long, default_value_t=default_params().iterations, help="Iteration depth (0 renders everything as part of the set)")]
     iterations: u32,
 
-    #[clap(short, long, default_value_t=10, help="Number of threads to use")]
+    #[clap(long, help="Greyscale image scaling the iteration depth per pixel, white being the full depth")]
+    iter_mask: Option<String>,
+
+    #[clap(long, default_value_t=default_params().bailout, help="Escape radius beyond which a point is considered escaped")]
+    bailout: f32,
+
+    #[clap(long, value_enum, default_value_t=default_params().bailout_shape, help="Shape of the region beyond which a point is considered escaped")]
+    bailout_shape: BailoutShape,
+
+    #[clap(long, default_value_t=default_params().power, help="Exponent of the iterated polynomial z^power + c")]
+    power: u32,
+
+    #[clap(short, long, default_value_t=default_params().threads, help="Number of threads to use")]
     threads: usize,
 
-    #[clap(short, long, default_value_t=0.5, help="The radius to examine")]
+    #[clap(long, help="Number of finished tiles that may wait to be merged before threads pause, 4 per thread by default")]
+    queue_depth: Option<usize>,
+
+    #[clap(long, default_value_t=default_params().tile_size, help="Edge length of the square tiles the image is split into")]
+    tile: u32,
+
+    #[clap(long, value_enum, default_value_t=default_params().schedule, help="Split tiles between threads up front (static) or hand them out as threads become idle (dynamic)")]
+    schedule: Schedule,
+
+    #[clap(long, conflicts_with_all=&["schedule", "refine"], help="Imply --no-optimizations and split the tiles statically between the threads, for golden image tests. Tiles do not depend on the thread count, so neither does the output")]
+    reproducible: bool,
+
+    #[clap(long, conflicts_with="refine", help="Iterate every sample in full without the interior, periodicity and symmetry shortcuts, as a reference")]
+    no_optimizations: bool,
+
+    #[clap(long, value_enum, default_value_t=default_params().tile_order, help="Order in which tiles are rendered, row by row or along a Z-order curve")]
+    tile_order: TileOrder,
+
+    #[clap(long, default_value_t=default_params().samples, help="Number of samples per pixel for anti-aliasing")]
+    samples: u32,
+
+    #[clap(long, value_enum, default_value_t=default_params().sample_pattern, help="Layout of the samples within each pixel")]
+    sample_pattern: SamplePattern,
+
+    #[clap(long, default_value_t=default_params().sample_offset, help="Position of each sample within its pixel (or grid cell), 0.5 is the center and 0 the top-left corner")]
+    sample_offset: f32,
+
+    #[clap(long, help="Smooth jagged edges with a cheap post-processing pass")]
+    post_aa: bool,
+
+    #[clap(short, long, default_value_t=default_params().radius.0, help="The radius to examine")]
     radius: f32,
 
+    #[clap(long, requires="radius-y", conflicts_with="radius", help="Radius along the real axis, overriding --radius")]
+    radius_x: Option<f32>,
+
+    #[clap(long, requires="radius-x", conflicts_with="radius", help="Radius along the imaginary axis, overriding --radius")]
+    radius_y: Option<f32>,
+
+    #[clap(long, help="Map angle around the center horizontally and log distance from it vertically, showing many zoom levels at once")]
+    log_polar: bool,
+
+    #[clap(long, default_value="-6,1", requires="log-polar", help="Range of the natural log of the distance from the center as min,max with --log-polar")]
+    log_radius: String,
+
+    #[clap(long, help="Additionally save a greyscale version as <name>.grey.png")]
+    also_greyscale: bool,
+
+    #[clap(long, help="Only draw the boundary of the set as black lines on white")]
+    edges: bool,
+
+    #[clap(long, default_value_t=0.05, help="Gradient of the normalized escape counts above which a pixel counts as an edge")]
+    edge_threshold: f32,
+
+    #[clap(long, value_enum, help="Only keep the interior or exterior of the set, making the rest transparent")]
+    only: Option<Region>,
+
+    #[clap(long, requires="only", help="Fade the --only cutout by the fraction of samples inside the region (use with --samples)")]
+    soft_edge: bool,
+
+    #[clap(long, help="Suggest a view zoomed onto the most detailed part of the image")]
+    center_on_feature: bool,
+
+    #[clap(long, default_value_t=8, help="Zoom factor of the view suggested by --center-on-feature")]
+    feature_zoom: u32,
+
+    #[clap(long, help="Save the computed escape data to this file for later recoloring")]
+    export_grid: Option<String>,
+
+    #[clap(long, help="Color a previously exported grid file instead of computing a new one")]
+    recolor: Option<String>,
+
+    #[clap(long, conflicts_with_all=&["center", "radius", "radius-x", "radius-y", "iterations", "preset", "goto", "recolor", "iter-mask"], help="Render the view of a previously exported single sample grid, reusing its samples wherever new ones land on exactly the same point. Power of two upscales need --sample-offset 0, odd factors centered samples")]
+    refine: Option<String>,
+
+    #[clap(long, help="Save signed distances to the boundary as a float image, e.g. OpenEXR, negative inside the set")]
+    export_sdf: Option<String>,
+
+    #[clap(long, help="Save a plot of the escape count distribution to this file")]
+    histogram_image: Option<String>,
+
+    #[clap(long, default_value="512x256", help="Dimensions of the histogram plot")]
+    histogram_size: String,
+
+    #[clap(long, help="Abort the render after this many seconds")]
+    timeout: Option<f32>,
+
+    #[clap(long, requires="timeout", help="Save whatever was computed when the render is aborted")]
+    write_partial: bool,
+
+    #[clap(long, default_value_t=0, help="Retry saving the output image this many times after I/O errors")]
+    save_retries: u32,
+
+    #[clap(long, help="Render a quick low resolution preview instead of the full image")]
+    preview: bool,
+
+    #[clap(long, help="Draw a marker at the center point of the output image")]
+    crosshair: bool,
+
+    #[clap(long, help="Overlay the complex plane axes and gridlines")]
+    axes: bool,
+
+    #[clap(long, default_value_t=1.0, help="Distance between gridlines in complex plane units")]
+    grid_spacing: f32,
+
+    #[clap(long, default_value="255,255,255", help="Color of the axes as r,g,b")]
+    axes_color: String,
+
+    #[clap(long, default_value="128,128,128", help="Color of the gridlines as r,g,b")]
+    grid_color: String,
+
+    #[clap(long, help="Draw this text onto the finished image")]
+    caption: Option<String>,
+
+    #[clap(long, value_enum, default_value="bottom-left", help="Corner the caption is drawn into")]
+    caption_pos: CaptionPosition,
+
+    #[clap(long, default_value_t=2, help="Scale of the caption font")]
+    caption_scale: u32,
+
+    #[clap(long, default_value="255,255,255", help="Color of the caption text as r,g,b")]
+    caption_color: String,
+
+    #[clap(long, default_value="0,0,0", help="Color of the box behind the caption as r,g,b")]
+    caption_box_color: String,
+
+    #[clap(long, default_value_t=0.6, help="Opacity of the box behind the caption, 0 leaves it out")]
+    caption_box_opacity: f32,
+
+    #[clap(long, help="Also save a thumbnail with the given maximum dimension")]
+    thumbnail: Option<u32>,
+
+    #[clap(long, help="Render this many images with evenly spaced iteration depths (teaching aid)")]
+    layers: Option<u32>,
+
+    #[clap(long, conflicts_with_all=&["center", "radius", "radius-x", "radius-y", "iterations"], help="Render one of the built-in locations")]
+    preset: Option<String>,
+
+    #[clap(long, help="File of saved viewports used by --label, --goto and --list-bookmarks")]
+    bookmark: Option<String>,
+
+    #[clap(long, requires="bookmark", conflicts_with="goto", help="Save the viewport to the --bookmark file under this label before rendering")]
+    label: Option<String>,
+
+    #[clap(long, requires="label", help="Replace an existing bookmark with the same label instead of failing")]
+    overwrite_bookmark: bool,
+
+    #[clap(long, requires="bookmark", conflicts_with_all=&["center", "radius", "radius-x", "radius-y", "iterations", "preset"], help="Render the viewport saved in the --bookmark file under this label")]
+    goto: Option<String>,
+
+    #[clap(long, requires="bookmark", help="Print the bookmarks in the --bookmark file and exit")]
+    list_bookmarks: bool,
+
+    #[clap(long, help="Render the Mandelbrot set next to the Julia set for --julia, marking where that constant lies")]
+    split_screen: bool,
+
+    #[clap(long, default_value_t=0.5, help="Fraction of the width taken up by the Mandelbrot set with --split-screen")]
+    split_ratio: f32,
+
+    #[clap(long, default_value_t=4, help="Pixels between the two halves with --split-screen")]
+    split_gap: u32,
+
+    #[clap(long, help="Render all built-in presets as a labelled grid of thumbnails")]
+    contact_sheet: bool,
+
+    #[clap(long, help="Only render the pixel rectangles listed in this file, each into its own image")]
+    regions: Option<String>,
+
+    #[clap(long, help="Render an animation interpolated between the keyframes in this file")]
+    keyframes: Option<String>,
+
+    #[clap(long, default_value_t=25.0, help="Frames per second for keyframe animations")]
+    fps: f32,
+
+    #[clap(long, conflicts_with="keyframes", help="Render one frame per iteration depth stepping from start to end as start,end,frames")]
+    iter_sweep: Option<String>,
+
+    #[clap(long, requires="keyframes", help="Encode the animation frames into this looping GIF instead of separate images")]
+    gif: Option<String>,
+
+    #[clap(long, requires="gif", help="Milliseconds each GIF frame is shown for, 1000 / fps by default")]
+    gif_delay: Option<u32>,
+
+    #[clap(long, number_of_values=2, value_names=&["A", "B"], help="Save the difference of two existing images instead of rendering")]
+    compare: Option<Vec<String>>,
+
+    #[clap(long, help="Render a small fixed scene, check that this build computes it correctly and exit")]
+    selftest: bool,
+
+    #[clap(long, default_value_t=1.0, help="Factor the differences are multiplied with in the --compare output")]
+    diff_amplify: f32,
+
     #[clap(default_value="fractal.png", help="Output file name")]
     name: String
 }
 
+/// Library defaults the command line options fall back to.
+fn default_params() -> RenderParams {
+    RenderBuilder::new().build().expect("the builder defaults are valid")
+}
+
+fn default_pair(first: impl std::fmt::Display, second: impl std::fmt::Display, separator: &str) -> String {
+    format!("{}{}{}", first, separator, second)
+}
+
 fn main() {
     let args = Args::parse();
+    interrupt::install();
+
+    if let Err(err) = run(args) {
+        // Renders cancelled by Ctrl-C outside of the main image report it
+        // the same way as the main image does.
+        let err = match err {
+            MandelbrotError::Cancelled(_) if interrupt::interrupted() => MandelbrotError::Interrupted,
+            err => err
+        };
+
+        eprintln!("Error: {}", err);
+        std::process::exit(err.exit_code());
+    }
+}
+
+fn run(args: Args) -> Result<(), MandelbrotError> {
+    if let Some(paths) = &args.compare {
+        return compare_files(&args, &paths[0], &paths[1]);
+    }
+
+    if args.selftest {
+        return run_selftest();
+    }
+
+    let (width, height) = match args.megapixels {
+        Some(megapixels) => util::megapixel_dimensions(megapixels, util::parse_tuple(&args.aspect, ":")?)?,
+        None => util::parse_tuple(&args.dimensions, "x")?
+    };
+    let center: (f32, f32) = util::parse_tuple(&args.center, ",")?;
+    let julia: (f32, f32) = util::parse_tuple(&args.julia, ",")?;
+
+    let radius = match (args.radius_x, args.radius_y) {
+        (Some(radius_x), Some(radius_y)) => (radius_x, radius_y),
+        _ => (args.radius, args.radius)
+    };
+
+    let sine_palette = if args.sine_palette || args.rgb_freqs.is_some() {
+        Some(SinePalette {
+            frequencies: match &args.rgb_freqs {
+                Some(frequencies) => util::parse_triple(frequencies)?,
+                None => SinePalette::RAINBOW.frequencies
+            },
+            phases: util::parse_triple(&args.rgb_phases)?
+        })
+    } else {
+        None
+    };
+
+    let gradient = match &args.palette_from_image {
+        Some(path) => Some(std::sync::Arc::new(util::palette_from_image(&image::open(path)?.to_rgb8(), args.palette_size)?)),
+        None => None
+    };
+
+    let mapping = if args.log_polar {
+        Mapping::LogPolar { log_radius: util::parse_tuple(&args.log_radius, ",")? }
+    } else {
+        Mapping::Linear
+    };
+
+    let no_optimizations = args.no_optimizations || args.reproducible;
 
-    let (width, height) = util::parse_tuple(args.dimensions, "x");
-    let center: (f32, f32) = util::parse_tuple(args.center, ",");
+    let mut params = RenderBuilder::new()
+        .dimensions(width, height)
+        .center(center.0, center.1)
+        .radii(radius.0, radius.1)
+        .mapping(mapping)
+        .fractal(args.fractal)
+        .iterations(args.iterations)
+        .convergence_eps(args.convergence_eps)
+        .julia(julia.0, julia.1)
+        .bailout(args.bailout)
+        .bailout_shape(args.bailout_shape)
+        .power(args.power)
+        .threads(args.threads)
+        .queue_depth(args.queue_depth)
+        .tile_size(args.tile)
+        .schedule(if args.reproducible { Schedule::Static } else { args.schedule })
+        .tile_order(args.tile_order)
+        .samples(args.samples)
+        .sample_pattern(args.sample_pattern)
+        .sample_offset(args.sample_offset)
+        .color(args.color)
+        .sine_palette(sine_palette)
+        .gradient(gradient)
+        .smooth(args.smooth)
+        .smooth_strength(args.smooth_strength)
+        .potential(args.potential)
+        .equipotential_bands(args.equipotential_bands)
+        .palette_shift(args.palette_shift)
+        .color_cycles(args.color_cycles)
+        .index_channel(args.index_channel)
+        .tonemap(args.tonemap)
+        .exposure(args.exposure)
+        .interior(args.interior_mode)
+        // Exported grids keep the exact final values for later recoloring.
+        .fast_paths(args.export_grid.is_none() && !no_optimizations)
+        .symmetry(!no_optimizations)
+        .build()?;
+
+    if let Some(name) = &args.preset {
+        params = presets::find_preset(name)?.apply(&params);
+    }
+
+    if let Some(path) = &args.bookmark {
+        if args.list_bookmarks {
+            for bookmark in bookmarks::read_bookmarks(path)? {
+                println!(
+                    "{}: -c={},{} --radius-x {} --radius-y {} -i {}",
+                    bookmark.label, bookmark.center.0, bookmark.center.1, bookmark.radius.0, bookmark.radius.1, bookmark.iterations
+                );
+            }
+
+            return Ok(());
+        }
+
+        match (&args.label, &args.goto) {
+            (Some(label), _) => {
+                bookmarks::save_bookmark(path, &bookmarks::Bookmark::from_params(label, &params), args.overwrite_bookmark)?;
+
+                if args.verbose {
+                    println!("=> Viewport saved to '{}' as '{}'", path, label);
+                }
+            },
+            (_, Some(label)) => params = bookmarks::find_bookmark(&bookmarks::read_bookmarks(path)?, label)?.apply(&params),
+            _ => return Err(MandelbrotError::InvalidParameter("--bookmark needs one of --label, --goto or --list-bookmarks".to_string()))
+        }
+    }
+
+    if let Some(path) = &args.refine {
+        let (header, grid) = gridfile::read_grid(path)?;
+        let view = header.apply(&params);
+
+        params = RenderParams {
+            width: params.width,
+            height: params.height,
+            samples: params.samples,
+            sample_offset: params.sample_offset,
+            mapping: params.mapping,
+            iteration_mask: params.iteration_mask.clone(),
+            ..view.clone()
+        };
+        params.prior = Some(std::sync::Arc::new(PriorGrid::new(view, grid, &params)?));
+    }
+
+    if let Some(iterations) = args.newton_iterations {
+        if params.fractal != Fractal::Newton {
+            return Err(MandelbrotError::InvalidParameter("--newton-iterations only applies to --fractal newton".to_string()));
+        }
+
+        params.newton_iterations = Some(iterations);
+    }
+
+    if args.preview {
+        let (preview_width, preview_height) = util::fit_dimensions(params.width, params.height, PREVIEW_SIZE);
+
+        println!(
+            "=> Preview only, the full render would be {}x{} ({} pixels) at iteration depth {}",
+            params.width, params.height, params.width as u64 * params.height as u64, params.iterations
+        );
+
+        params.width = preview_width;
+        params.height = preview_height;
+        params.iterations = params.iterations.min(PREVIEW_ITERATIONS);
+    }
+
+    if let Some(path) = &args.iter_mask {
+        let mask = util::load_iteration_mask(path, params.width, params.height, params.iterations)?;
+        params.iteration_mask = Some(std::sync::Arc::new(mask));
+    }
+
+    if let Some(warning) = util::iteration_warning(params.iterations) {
+        eprintln!("Warning: {}", warning);
+    }
+
+    let deadline = match args.timeout {
+        Some(timeout) if !(timeout.is_finite() && timeout > 0.0) => {
+            return Err(MandelbrotError::InvalidParameter(format!("timeout must be a positive number, got {}", timeout)));
+        },
+        Some(timeout) => Some(std::time::Instant::now() + std::time::Duration::from_secs_f32(timeout)),
+        None => None
+    };
+
+    params.cancel = Some(watchdog(deadline));
 
     let start = Instant::now();
-    let pool = ThreadPool::new(args.threads);
-    let (tx, rx) = channel();
 
-    let mut imgbuf = image::ImageBuffer::new(width, height);
+    if args.contact_sheet {
+        let sheet = presets::render_contact_sheet(&params, args.thumbnail.unwrap_or(160))?;
+        image::DynamicImage::ImageRgb8(sheet).save(Path::new(&args.name))?;
+
+        if args.verbose {
+            println!("=> Contact sheet saved as '{}'", args.name);
+            println!("=> Time taken: {:.2}s", start.elapsed().as_seconds_f64());
+        }
+
+        return Ok(());
+    }
+
+    if args.split_screen {
+        montage::render_split(&params, args.split_ratio, args.split_gap)?.save(Path::new(&args.name))?;
+
+        if args.verbose {
+            println!("=> Split screen image saved as '{}'", args.name);
+            println!("=> Time taken: {:.2}s", start.elapsed().as_seconds_f64());
+        }
+
+        return Ok(());
+    }
+
+    if let Some(path) = &args.keyframes {
+        let keyframes = animation::parse_keyframes(&std::fs::read_to_string(path)?)?;
+
+        let mut gif = match &args.gif {
+            Some(path) => Some(animation::gif_encoder(path)?),
+            None => None
+        };
+        let delay = args.gif_delay.unwrap_or((1000.0 / args.fps).round() as u32);
+
+        animation::render_animation(&params, &keyframes, args.fps, |index, imgbuf| {
+            if let Some(encoder) = &mut gif {
+                animation::encode_gif_frame(encoder, imgbuf, delay)?;
+
+                if args.verbose {
+                    println!("=> Frame {} added to the GIF", index);
+                }
+
+                return Ok(());
+            }
+
+            let name = animation::frame_name(&args.name, index);
+            image::DynamicImage::ImageRgb8(imgbuf).save(Path::new(&name))?;
+
+            if args.verbose {
+                println!("=> Frame {} saved as '{}'", index, name);
+            }
+
+            Ok(())
+        })?;
+
+        if let (Some(path), true) = (&args.gif, args.verbose) {
+            println!("=> Animation saved as '{}'", path);
+        }
+
+        if args.verbose {
+            println!("=> Time taken: {:.2}s", start.elapsed().as_seconds_f64());
+        }
+
+        return Ok(());
+    }
+
+    if let Some(sweep) = &args.iter_sweep {
+        let levels = animation::parse_sweep(sweep)?;
+
+        animation::render_sweep(&params, &levels, |index, imgbuf| {
+            let name = animation::frame_name(&args.name, index);
+            image::DynamicImage::ImageRgb8(imgbuf).save(Path::new(&name))?;
+
+            if args.verbose {
+                println!("=> Frame {} with iteration depth {} saved as '{}'", index, levels[index as usize], name);
+            }
+
+            Ok(())
+        })?;
+
+        if args.verbose {
+            println!("=> Time taken: {:.2}s", start.elapsed().as_seconds_f64());
+        }
+
+        return Ok(());
+    }
+
+    if let Some(path) = &args.regions {
+        for region in regions::parse_regions(&std::fs::read_to_string(path)?)? {
+            let name = regions::region_name(&args.name, &region);
+            render::render_region(&params, &region)?.save(Path::new(&name))?;
+
+            if args.verbose {
+                println!("=> Region {}x{} at ({}, {}) saved as '{}'", region.width, region.height, region.x, region.y, name);
+            }
+        }
+
+        if args.verbose {
+            println!("=> Time taken: {:.2}s", start.elapsed().as_seconds_f64());
+        }
+
+        return Ok(());
+    }
+
+    if let Some(layers) = args.layers {
+        render_layers(&args, &params, layers)?;
+
+        if args.verbose {
+            println!("=> Time taken: {:.2}s", start.elapsed().as_seconds_f64());
+        }
+
+        return Ok(());
+    }
+
+    let loaded_grid = match &args.recolor {
+        Some(path) => {
+            if matches!(params.index_channel, IndexChannel::Distance | IndexChannel::OrbitTrap) {
+                return Err(MandelbrotError::InvalidParameter(
+                    "grid files do not hold the distance or orbit trap channels, recolor them by iteration or potential".to_string()
+                ));
+            }
+
+            let (header, grid) = gridfile::read_grid(path)?;
+            params = header.apply(&params);
+            Some(grid)
+        },
+        None => None
+    };
 
     if args.verbose {
         println!(
-            "=> Generating output image of size {}x{} at point ({}, {}) with radius {} and iteration depth {}...",
-            width, height, center.0, center.1, args.radius, args.iterations
+            "=> Generating output image of size {}x{} at point ({}, {}) with radius {}x{} and iteration depth {}...",
+            params.width, params.height,
+            util::format_coordinate(params.center.0, params.radius.0, params.width),
+            util::format_coordinate(params.center.1, params.radius.1, params.height),
+            params.radius.0, params.radius.1, params.iterations
         );
     }
 
-    for x in 0..width {
-        for y in 0..height {
-            let tx = tx.clone();
+    let total = params.width as u64 * params.height as u64;
+    let mut progress = ProgressBar::new(total);
+    let mut rate = progress::RateEstimator::with_start(progress::RATE_WINDOW, std::time::Instant::now());
+    let mut done = 0;
 
-            pool.execute(move|| {
-                let c = Complex::new(
-                    ((x as f32 * args.radius / width as f32) - args.radius / 2.0) + center.0,
-                    -((y as f32 * args.radius / height as f32) - args.radius / 2.0) + center.1
-                );
+    // The bar's own estimate averages over the whole render, show the one
+    // over the recent rate instead.
+    progress.show_time_left = false;
+
+    let mut json_progress = match &args.progress_json {
+        Some(destination) => Some(progress::JsonProgress::open(destination, total)?),
+        None => None
+    };
+
+    let stats = std::sync::Arc::new(RenderStats::default());
+    params.stats = Some(stats.clone());
+
+    let (grid, complete) = match loaded_grid {
+        Some(grid) => (grid, true),
+        None => compute_partial_grid(&params, |count| {
+            if args.verbose {
+                done += count;
+                rate.record(std::time::Instant::now(), done);
+
+                if let Some(eta) = rate.eta(total) {
+                    progress.message(&format!("ETA {:.0}s ", eta.ceil()));
+                }
+
+                progress.add(count);
+            }
+
+            // A reader going away must not abort the render itself.
+            if let Some(json_progress) = &mut json_progress {
+                let _ = json_progress.add(count);
+            }
+        })?
+    };
+
+    if let Some(json_progress) = &mut json_progress {
+        let _ = if complete { json_progress.finish() } else { json_progress.stop() };
+    }
+
+    let fast_path_tiles = stats.fast_path_tiles.load(Ordering::Relaxed);
+    let reused_samples = stats.reused_samples.load(Ordering::Relaxed);
+
+    if args.verbose && fast_path_tiles > 0 {
+        println!("\n=> {} tiles took the interior fast paths", fast_path_tiles);
+    }
+
+    if args.verbose && reused_samples > 0 {
+        println!("=> {} samples were taken from the refined grid", reused_samples);
+    }
+
+    if !complete {
+        if args.verbose {
+            progress.finish();
+        }
+
+        // Ctrl-C always keeps what was computed, a timeout only when asked to.
+        if args.write_partial || interrupt::interrupted() {
+            colorize(&grid, &params).save(Path::new(&args.name))?;
+            eprintln!("=> Partial image saved as '{}'", args.name);
+        }
+
+        if interrupt::interrupted() {
+            return Err(MandelbrotError::Interrupted);
+        }
+
+        return Err(MandelbrotError::Cancelled(format!("exceeded the timeout of {}s", args.timeout.unwrap_or(0.0))));
+    }
+
+    if args.center_on_feature {
+        suggest_feature(&args, &grid, &params);
+    }
+
+    if let Some(path) = &args.histogram_image {
+        let (histogram_width, histogram_height) = util::parse_tuple(&args.histogram_size, "x")?;
+        let counts = histogram::escape_histogram(&grid, params.iterations);
+
+        histogram::draw_histogram(&counts, histogram_width, histogram_height).save(Path::new(path))?;
 
-                let point = MandelbrotPoint{
-                    x: x, y: y,
-                    color: get_mandelbrot_color(c, args.iterations, args.color)
-                };
+        if args.verbose {
+            println!("=> Histogram saved as '{}'", path);
+        }
+    }
+
+    if let Some(path) = &args.export_grid {
+        gridfile::write_grid(path, &grid, &params)?;
 
-                tx.send(point)
-                  .expect("Could not send");
-            });
+        if args.verbose {
+            println!("=> Escape grid saved as '{}'", path);
         }
     }
 
-    let mut progress = ProgressBar::new((width * height) as u64);
-    let mut count = 0;
+    if let Some(path) = &args.export_sdf {
+        sdf::sdf_image(&params)?.save(Path::new(path))?;
 
-    rx.iter().take((width * height) as usize).for_each(|point| {
-        if point.color != image::Rgb([0, 0, 0]) {
-            imgbuf.put_pixel(point.x, point.y, point.color);
+        if args.verbose {
+            println!("=> Signed distances saved as '{}'", path);
         }
+    }
+
+    let is_exr = Path::new(&args.name).extension().is_some_and(|extension| extension.eq_ignore_ascii_case("exr"));
 
-        if args.verbose && count % 10000 == 0 {
-            progress.add(10000);
+    if is_exr {
+        render::normalized_escapes(&grid, &params).save(Path::new(&args.name))?;
+
+        if args.verbose {
+            progress.finish();
+            println!("=> Normalized escape counts saved as '{}'", args.name);
+            println!("=> Time taken: {:.2}s", start.elapsed().as_seconds_f64());
         }
-        count += 1;
-    });
+
+        return Ok(());
+    }
+
+    let mut imgbuf = if args.edges {
+        edges::render_edges(&grid, params.iterations, args.edge_threshold)
+    } else {
+        colorize(&grid, &params)
+    };
+
+    let repeated_rows = stats.repeated_rows.load(Ordering::Relaxed);
+
+    if args.verbose && repeated_rows > 0 {
+        println!("=> {} rows repeated the one above and reused its colors", repeated_rows);
+    }
+
+    if args.post_aa {
+        imgbuf = filter::post_antialias(&imgbuf);
+    }
+
+    let center_point = num::complex::Complex::new(params.center.0, params.center.1);
+    let center_in_set = escape(center_point, &params).in_set;
+
+    if let Some(hint) = util::uniform_image_hint(&imgbuf, center_in_set) {
+        eprintln!("Hint: {}", hint);
+    }
+
+    if args.axes {
+        let axes_color = util::parse_color(&args.axes_color)?;
+        let grid_color = util::parse_color(&args.grid_color)?;
+
+        overlay::draw_axes(&mut imgbuf, &params, args.grid_spacing, axes_color, grid_color);
+    }
+
+    if args.crosshair {
+        util::draw_crosshair(&mut imgbuf, image::Rgb([255, 0, 0]));
+    }
+
+    if let Some(caption) = &args.caption {
+        let style = CaptionStyle {
+            scale: args.caption_scale,
+            color: util::parse_color(&args.caption_color)?,
+            box_color: util::parse_color(&args.caption_box_color)?,
+            box_opacity: args.caption_box_opacity
+        };
+
+        overlay::draw_caption(&mut imgbuf, caption, args.caption_pos, &style);
+    }
 
     if args.verbose {
         progress.finish();
         println!("=> Saving output image...");
     }
 
-    let _ = image::DynamicImage::ImageRgb8(imgbuf).save(Path::new(&args.name));
+    if let Some(size) = args.thumbnail {
+        let thumbnail_name = util::thumbnail_name(&args.name);
+        util::make_thumbnail(&imgbuf, size).save(Path::new(&thumbnail_name))?;
+
+        if args.verbose {
+            println!("=> Thumbnail saved as '{}'", thumbnail_name);
+        }
+    }
+
+    if args.also_greyscale {
+        let grey_name = util::suffixed_name(&args.name, ".grey");
+        let grey_params = RenderParams { color: false, ..params.clone() };
+
+        colorize(&grid, &grey_params).save(Path::new(&grey_name))?;
+
+        if args.verbose {
+            println!("=> Greyscale image saved as '{}'", grey_name);
+        }
+    }
+
+    let output = match args.only {
+        Some(region) => image::DynamicImage::ImageRgba8(render::isolate_region(&imgbuf, &grid, region, args.soft_edge)),
+        None => image::DynamicImage::ImageRgb8(imgbuf)
+    };
+
+    util::save_with_retries(args.save_retries, || Ok(output.save(Path::new(&args.name))?))?;
 
     if args.verbose {
         println!("=> Output image saved as '{}'", args.name);
         println!("=> Time taken: {:.2}s", start.elapsed().as_seconds_f64());
     }
+
+    Ok(())
+}
+
+/// Starts a thread raising the returned flag once Ctrl-C is pressed or the
+/// deadline passes, whichever comes first.
+fn watchdog(deadline: Option<std::time::Instant>) -> std::sync::Arc<std::sync::atomic::AtomicBool> {
+    let cancel = std::sync::Arc::new(std::sync::atomic::AtomicBool::new(false));
+    let flag = cancel.clone();
+
+    std::thread::spawn(move|| {
+        loop {
+            let expired = match deadline {
+                Some(deadline) => std::time::Instant::now() >= deadline,
+                None => false
+            };
+
+            if expired || interrupt::interrupted() {
+                break;
+            }
+
+            std::thread::sleep(WATCHDOG_INTERVAL);
+        }
+
+        flag.store(true, std::sync::atomic::Ordering::Relaxed);
+    });
+
+    cancel
+}
+
+/// Prints the view onto the window of the grid with the most detail.
+fn suggest_feature(args: &Args, grid: &render::EscapeGrid, params: &RenderParams) {
+    let size = (params.width.min(params.height) / args.feature_zoom.max(1)).max(1);
+
+    let found = match feature::find_feature(grid, size) {
+        Some(found) if found.variance > 0.0 => found,
+        _ => {
+            eprintln!("Hint: no detail found to center on, the whole view looks uniform");
+            return;
+        }
+    };
+
+    let center = params.point(found.x as f32 + size as f32 / 2.0, found.y as f32 + size as f32 / 2.0);
+    let radius = (params.radius.0 * size as f32 / params.width as f32, params.radius.1 * size as f32 / params.height as f32);
+
+    let dimensions = if radius.0 == radius.1 {
+        format!("-r {}", radius.0)
+    } else {
+        format!("--radius-x {} --radius-y {}", radius.0, radius.1)
+    };
+
+    println!(
+        "=> Most detail around pixel ({}, {}), try: -c={},{} {}",
+        found.x + size / 2, found.y + size / 2,
+        util::format_coordinate(center.re, radius.0, size),
+        util::format_coordinate(center.im, radius.1, size),
+        dimensions
+    );
+}
+
+/// Prints the outcome of every self-test check, failing if any of them did.
+fn run_selftest() -> Result<(), MandelbrotError> {
+    let checks = selftest::run_selftest()?;
+    let failed = checks.iter().filter(|check| !check.passed).count();
+
+    for check in &checks {
+        println!("{} {} ({})", if check.passed { "PASS" } else { "FAIL" }, check.name, check.detail);
+    }
+
+    if failed > 0 {
+        return Err(MandelbrotError::Render(format!("{} of {} self-test checks failed", failed, checks.len())));
+    }
+
+    println!("=> All {} self-test checks passed", checks.len());
+
+    Ok(())
+}
+
+/// Saves the difference of two images and prints how much they deviate.
+fn compare_files(args: &Args, a: &str, b: &str) -> Result<(), MandelbrotError> {
+    let (diff, stats) = compare::compare_images(&image::open(a)?.to_rgb8(), &image::open(b)?.to_rgb8(), args.diff_amplify)?;
+
+    println!(
+        "=> Max delta: {}, mean delta: {:.4}, differing pixels: {}",
+        stats.max_delta, stats.mean_delta, stats.differing_pixels
+    );
+
+    diff.save(Path::new(&args.name))?;
+
+    if args.verbose {
+        println!("=> Difference image saved as '{}'", args.name);
+    }
+
+    Ok(())
+}
+
+/// Renders the same view once per iteration depth to show how detail emerges
+/// as the iteration cap grows. This is mostly useful as a teaching aid.
+fn render_layers(args: &Args, params: &RenderParams, layers: u32) -> Result<(), MandelbrotError> {
+    if layers == 0 {
+        return Err(MandelbrotError::InvalidParameter("number of layers must be at least 1".to_string()));
+    }
+
+    for iterations in util::layer_levels(params.iterations, layers) {
+        let params = RenderParams { iterations, ..params.clone() };
+        let name = util::suffixed_name(&args.name, &format!("_iter_{:04}", iterations));
+
+        let mut imgbuf = render(&params, |_| {})?;
+
+        if args.crosshair {
+            util::draw_crosshair(&mut imgbuf, image::Rgb([255, 0, 0]));
+        }
+
+        image::DynamicImage::ImageRgb8(imgbuf).save(Path::new(&name))?;
+
+        if args.verbose {
+            println!("=> Layer with iteration depth {} saved as '{}'", iterations, name);
+        }
+    }
+
+    Ok(())
 }
@@ -1,27 +1,356 @@
 use num::complex::Complex;
 
+use crate::render::RenderParams;
+
 pub struct MandelbrotPoint {
     pub x: u32,
     pub y: u32,
     pub color: image::Rgb<u8>
 }
 
-fn mandelbrot(z: Complex<f32>, c: Complex<f32>) -> Complex<f32> {
-    num::pow(z, 2) + c
+/// Outcome of iterating a single point: whether it stayed bounded, how many
+/// iterations that took and the final value of `z`.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Escape {
+    pub in_set: bool,
+    pub iterations: u32,
+    pub z: Complex<f32>,
+    /// Value of the index channel for escaped points, if it is one that the
+    /// other fields do not determine. 0 otherwise.
+    pub channel: f32
+}
+
+/// Quantity of an escaped point that picks its place in the palette.
+///
+/// `Iteration` is the escape count, smooth or continuous as configured, and
+/// `Potential` always the continuous potential. `Distance` is the estimated
+/// distance to the boundary of the set, so that filaments too thin to hit a
+/// sample still show up, and `OrbitTrap` how close the orbit came to the
+/// origin. The last two only exist while iterating and are kept in
+/// `Escape::channel`.
+#[derive(clap::ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum IndexChannel {
+    Iteration,
+    Potential,
+    Distance,
+    OrbitTrap
+}
+
+#[derive(clap::ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum InteriorMode {
+    Solid,
+    Angle
+}
+
+#[derive(clap::ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Fractal {
+    Mandelbrot,
+    Julia,
+    Newton,
+    BurningShip,
+    Tricorn
+}
+
+/// One step of an escape-time fractal, `z → step(transform(z), c)`.
+pub trait FractalFormula {
+    /// Applied to `z` before every step. The identity by default.
+    fn transform(&self, z: Complex<f32>) -> Complex<f32> {
+        z
+    }
+
+    fn step(&self, z: Complex<f32>, c: Complex<f32>) -> Complex<f32>;
+}
+
+/// `z^power + c`, which is the Mandelbrot set for a power of 2.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Multibrot {
+    pub power: u32
+}
+
+/// `z^power + c` iterated on the absolute values of both components.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct BurningShip {
+    pub power: u32
+}
+
+/// `z^power + c` iterated on the complex conjugate of `z`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Tricorn {
+    pub power: u32
+}
+
+fn raise(z: Complex<f32>, power: u32) -> Complex<f32> {
+    if power == 2 {
+        // Matches `z * z` exactly but skips the general multiplication.
+        let (re, im) = (z.re, z.im);
+        Complex::new(re * re - im * im, 2.0 * re * im)
+    } else {
+        z.powu(power)
+    }
+}
+
+impl FractalFormula for Multibrot {
+    fn step(&self, z: Complex<f32>, c: Complex<f32>) -> Complex<f32> {
+        raise(z, self.power) + c
+    }
+}
+
+impl FractalFormula for BurningShip {
+    fn transform(&self, z: Complex<f32>) -> Complex<f32> {
+        Complex::new(z.re.abs(), z.im.abs())
+    }
+
+    fn step(&self, z: Complex<f32>, c: Complex<f32>) -> Complex<f32> {
+        raise(z, self.power) + c
+    }
+}
+
+impl FractalFormula for Tricorn {
+    fn transform(&self, z: Complex<f32>) -> Complex<f32> {
+        z.conj()
+    }
+
+    fn step(&self, z: Complex<f32>, c: Complex<f32>) -> Complex<f32> {
+        raise(z, self.power) + c
+    }
+}
+
+/// Region outside of which a point counts as escaped. Squares and diamonds
+/// let points escape a little sooner or later than the usual circle
+/// depending on their direction, which bends the bands of the exterior.
+#[derive(clap::ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum BailoutShape {
+    Circle,
+    Square,
+    Diamond
 }
 
-pub fn in_mandelbrot_set(c: Complex<f32>, iterations: u32) -> (bool, u32) {
+impl BailoutShape {
+    /// Whether `z` lies outside the shape of the given radius.
+    #[inline]
+    pub fn escaped(self, z: Complex<f32>, bailout: f32) -> bool {
+        match self {
+            BailoutShape::Circle => z.norm_sqr() > bailout * bailout,
+            BailoutShape::Square => z.re.abs().max(z.im.abs()) > bailout,
+            BailoutShape::Diamond => z.re.abs() + z.im.abs() > bailout
+        }
+    }
+
+    /// Whether the shape of the given radius holds the whole disc of radius
+    /// `radius`, so that no orbit staying within that disc ever escapes it.
+    pub fn contains_disc(self, bailout: f32, radius: f32) -> bool {
+        match self {
+            BailoutShape::Circle | BailoutShape::Square => bailout >= radius,
+            BailoutShape::Diamond => bailout >= radius * std::f32::consts::SQRT_2
+        }
+    }
+}
+
+pub fn in_mandelbrot_set(c: Complex<f32>, iterations: u32, bailout: f32, shape: BailoutShape, power: u32) -> (bool, u32, Complex<f32>) {
+    if power == 2 && shape == BailoutShape::Circle {
+        return iterate_quadratic(c, c, iterations, bailout, false);
+    }
+
+    iterate(&Multibrot { power }, c, c, iterations, bailout, shape)
+}
+
+/// Same iteration as `in_mandelbrot_set`, but starting at `z` with the
+/// constant `k` added in every step.
+pub fn in_julia_set(z: Complex<f32>, k: Complex<f32>, iterations: u32, bailout: f32, shape: BailoutShape, power: u32) -> (bool, u32, Complex<f32>) {
+    if power == 2 && shape == BailoutShape::Circle {
+        return iterate_quadratic(z, k, iterations, bailout, false);
+    }
+
+    iterate(&Multibrot { power }, z, k, iterations, bailout, shape)
+}
+
+/// `iterate` for `z² + c` with a circular bailout, which squares both
+/// components once per step and uses them for the bailout test as well as
+/// the next value of `z`. With `periodic` set it also stops on periodic
+/// orbits like `iterate_periodic`.
+fn iterate_quadratic(start: Complex<f32>, c: Complex<f32>, iterations: u32, bailout: f32, periodic: bool) -> (bool, u32, Complex<f32>) {
+    let bailout_squared = bailout * bailout;
+    let (mut re, mut im) = (start.re, start.im);
+    let mut saved = (re, im);
+    let mut period = 1;
+
+    for i in 0..iterations {
+        let (re2, im2) = (re * re, im * im);
+
+        if re2 + im2 > bailout_squared {
+            return (false, i, Complex::new(re, im));
+        }
+
+        im = 2.0 * re * im + c.im;
+        re = re2 - im2 + c.re;
+
+        if periodic {
+            if (re, im) == saved {
+                return (true, iterations, Complex::new(re, im));
+            }
+
+            if i + 1 == period {
+                saved = (re, im);
+                period *= 2;
+            }
+        }
+    }
+
+    (true, iterations, Complex::new(re, im))
+}
+
+/// Iterates `formula` from `start` until `z` leaves the bailout shape or the
+/// iteration depth is reached.
+pub fn iterate<F: FractalFormula>(formula: &F, start: Complex<f32>, c: Complex<f32>, iterations: u32, bailout: f32, shape: BailoutShape) -> (bool, u32, Complex<f32>) {
+    let mut z = start;
+
+    for i in 0..iterations {
+        if shape.escaped(z, bailout) {
+            return (false, i, z);
+        }
+
+        z = formula.step(formula.transform(z), c);
+    }
+
+    (true, iterations, z)
+}
+
+/// Same as `iterate`, but also stops once the orbit returns exactly to a
+/// value it had before, which means it is periodic and will never escape.
+/// Points inside the set then end with a different `z` than the full
+/// iteration would give, everything else is unchanged.
+pub fn iterate_periodic<F: FractalFormula>(formula: &F, start: Complex<f32>, c: Complex<f32>, iterations: u32, bailout: f32, shape: BailoutShape) -> (bool, u32, Complex<f32>) {
+    let mut z = start;
+    let mut saved = start;
+    let mut period = 1;
+
+    for i in 0..iterations {
+        if shape.escaped(z, bailout) {
+            return (false, i, z);
+        }
+
+        z = formula.step(formula.transform(z), c);
+
+        if z == saved {
+            return (true, iterations, z);
+        }
+
+        // Brent's method: compare against a value saved at every power of
+        // two, which catches cycles of any length.
+        if i + 1 == period {
+            saved = z;
+            period *= 2;
+        }
+    }
+
+    (true, iterations, z)
+}
+
+/// Whether `c` lies in the main cardioid or the period 2 bulb of the
+/// quadratic Mandelbrot set, both of which are entirely inside the set.
+pub fn in_cardioid_or_bulb(c: Complex<f32>) -> bool {
+    let shifted = c.re - 0.25;
+    let q = shifted * shifted + c.im * c.im;
+
+    q * (q + shifted) <= 0.25 * c.im * c.im || (c.re + 1.0) * (c.re + 1.0) + c.im * c.im <= 1.0 / 16.0
+}
+
+/// Runs Newton's method for `z³ - 1` starting at `c` until the orbit comes
+/// within `epsilon` of one of the three cube roots of unity. Points that do
+/// not converge within the iteration depth are reported as in the set.
+pub fn newton_converges(c: Complex<f32>, iterations: u32, epsilon: f32) -> (bool, u32, Complex<f32>) {
+    let roots = [
+        Complex::new(1.0, 0.0),
+        Complex::new(-0.5, 0.75f32.sqrt()),
+        Complex::new(-0.5, -(0.75f32.sqrt()))
+    ];
+
+    let epsilon_squared = epsilon * epsilon;
     let mut z = c;
 
     for i in 0..iterations {
-        if num::pow(z.re, 2) + num::pow(z.im, 2) > 4.0 {
-            return (false, i);
+        if roots.iter().any(|root| (z - root).norm_sqr() < epsilon_squared) {
+            return (true, i, z);
         }
 
-        z = mandelbrot(z, c);
+        let z2 = z * z;
+
+        if z2.norm_sqr() == 0.0 {
+            break;
+        }
+
+        z -= (z2 * z - 1.0) / (3.0 * z2);
     }
 
-    (true, iterations)
+    (false, iterations, z)
+}
+
+/// Fractional escape count for an escaped point. The usual
+/// `log(log|z|)/log(2)` only holds for a bailout of 2 and a power of 2, so
+/// the general form is used to keep the gradient continuous for other values.
+pub fn smooth_iterations(iterations_taken: u32, z: Complex<f32>, bailout: f32, power: u32) -> f32 {
+    let log_ratio = z.norm().ln() / bailout.ln();
+
+    iterations_taken as f32 + 1.0 - log_ratio.ln() / (power as f32).ln()
+}
+
+/// Logarithm of the continuous potential `G(c) = ln|z_n| / power^n` of an
+/// escaped point, taken in log space so that deep escapes do not underflow.
+fn log_potential(iterations_taken: u32, z: Complex<f32>, power: u32) -> f32 {
+    z.norm().ln().ln() - iterations_taken as f32 * (power as f32).ln()
+}
+
+/// Continuous escape-time potential (the Green's function of the set's
+/// complement) of an escaped point. It drops towards 0 approaching the set
+/// and, unlike the smooth iteration count, does not depend on the bailout as
+/// long as that is large: `log|z_n| / power^n` only approximates the
+/// potential once `|z_n|` dwarfs `|c|`, so a bailout of 2 leaves visible
+/// steps wherever the escape count changes.
+pub fn potential(iterations_taken: u32, z: Complex<f32>, power: u32) -> f32 {
+    log_potential(iterations_taken, z, power).exp()
+}
+
+/// Smallest `|z|` along the first `steps` iterations of an orbit.
+fn orbit_trap<F: FractalFormula>(formula: &F, start: Complex<f32>, c: Complex<f32>, steps: u32) -> f32 {
+    let mut z = start;
+    let mut closest = z.norm_sqr();
+
+    for _ in 0..steps {
+        z = formula.step(formula.transform(z), c);
+        closest = closest.min(z.norm_sqr());
+    }
+
+    closest.sqrt()
+}
+
+/// Exterior distance estimate `|z| ln|z| / |dz|` after `steps` iterations of
+/// `z^power + c`, with `dz` the derivative of `z` with respect to the pixel
+/// coordinate: `c` for the Mandelbrot set and the starting point for Julia
+/// sets.
+fn distance_estimate(start: Complex<f32>, c: Complex<f32>, steps: u32, power: u32, julia: bool) -> f32 {
+    let mut z = start;
+    let mut dz = Complex::new(1.0, 0.0);
+
+    for _ in 0..steps {
+        dz = raise(z, power - 1) * dz * power as f32 + if julia { 0.0 } else { 1.0 };
+        z = raise(z, power) + c;
+    }
+
+    let norm = z.norm();
+    norm * norm.ln() / dz.norm()
+}
+
+/// `Escape::channel` of an escaped point at `c` after `steps` iterations.
+fn channel_value(c: Complex<f32>, params: &RenderParams, steps: u32) -> f32 {
+    match (params.index_channel, params.fractal) {
+        (IndexChannel::Distance, Fractal::Mandelbrot) => distance_estimate(c, c, steps, params.power, false),
+        (IndexChannel::Distance, Fractal::Julia) => distance_estimate(c, params.julia, steps, params.power, true),
+        (IndexChannel::OrbitTrap, Fractal::Mandelbrot) => orbit_trap(&Multibrot { power: params.power }, c, c, steps),
+        (IndexChannel::OrbitTrap, Fractal::Julia) => orbit_trap(&Multibrot { power: params.power }, c, params.julia, steps),
+        (IndexChannel::OrbitTrap, Fractal::BurningShip) => orbit_trap(&BurningShip { power: params.power }, c, c, steps),
+        (IndexChannel::OrbitTrap, Fractal::Tricorn) => orbit_trap(&Tricorn { power: params.power }, c, c, steps),
+        _ => 0.0
+    }
 }
 
 fn get_greyscale_pixel(ratio: f32) -> image::Rgb<u8> {
@@ -34,26 +363,533 @@ fn get_greyscale_pixel(ratio: f32) -> image::Rgb<u8> {
     ])
 }
 
+fn get_hue_pixel(hue: f32) -> image::Rgb<u8> {
+    let h = hue.rem_euclid(1.0) * 6.0;
+    let x = 1.0 - (h % 2.0 - 1.0).abs();
+
+    let (r, g, b) = match h as u32 {
+        0 => (1.0, x, 0.0),
+        1 => (x, 1.0, 0.0),
+        2 => (0.0, 1.0, x),
+        3 => (0.0, x, 1.0),
+        4 => (x, 0.0, 1.0),
+        _ => (1.0, 0.0, x)
+    };
+
+    image::Rgb([
+        (r * 255.0) as u8,
+        (g * 255.0) as u8,
+        (b * 255.0) as u8
+    ])
+}
+
+/// Maps the ratio onto a rainbow running from red through to violet. The sweep
+/// stops short of a full turn so the two ends of the range stay distinct.
 fn get_color_pixel(ratio: f32) -> image::Rgb<u8> {
-    let color_value = (ratio * 0xFFFFFF as f32) as u32;
+    get_hue_pixel(ratio * 0.8)
+}
+
+/// Curve compressing escape ratios, scaled by an exposure, into the palette
+/// range. `Linear` clips everything the exposure pushes past the end of the
+/// palette, while `Reinhard` and `Aces` roll off towards it, so raising the
+/// exposure brings out dim regions without flattening the bright ones.
+#[derive(clap::ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Tonemap {
+    Linear,
+    Reinhard,
+    Aces
+}
+
+impl Tonemap {
+    /// Maps `ratio * exposure` into `[0, 1]`. The curves are scaled so that
+    /// a ratio of 1 still ends up at the end of the palette.
+    pub fn apply(self, ratio: f32, exposure: f32) -> f32 {
+        let value = ratio.max(0.0) * exposure;
+
+        let mapped = match self {
+            Tonemap::Linear => value,
+            Tonemap::Reinhard => value / (1.0 + value) * (1.0 + exposure) / exposure,
+            Tonemap::Aces => aces(value) / aces(exposure)
+        };
+
+        mapped.clamp(0.0, 1.0)
+    }
+}
+
+/// Narkowicz's fit of the ACES filmic curve.
+fn aces(value: f32) -> f32 {
+    (value * (2.51 * value + 0.03)) / (value * (2.43 * value + 0.59) + 0.14)
+}
 
-    let r = ((color_value & 0xFF0000) >> 16) as u8;
-    let g = ((color_value & 0x00FF00) >> 8) as u8;
-    let b = (color_value & 0x0000FF) as u8;
+/// Palette driving each color channel by its own cosine of the escape ratio,
+/// `0.5 + 0.5 * cos(2π * (frequency * ratio + phase))`.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct SinePalette {
+    pub frequencies: [f32; 3],
+    pub phases: [f32; 3]
+}
 
-    image::Rgb([r, g, b])
+impl SinePalette {
+    /// One period per channel, a third of a period apart, which runs through
+    /// the hues of a rainbow once.
+    pub const RAINBOW: SinePalette = SinePalette { frequencies: [1.0; 3], phases: [0.0, 0.33, 0.67] };
 }
 
-pub fn get_mandelbrot_color(c: Complex<f32>, iterations: u32, color: bool) -> image::Rgb<u8> {
-    let (in_set, iterations_taken) = in_mandelbrot_set(c, iterations);
+fn get_sine_pixel(ratio: f32, palette: &SinePalette) -> image::Rgb<u8> {
+    let mut channels = [0u8; 3];
+
+    for (i, channel) in channels.iter_mut().enumerate() {
+        let angle = 2.0 * std::f32::consts::PI * (palette.frequencies[i] * ratio + palette.phases[i]);
+        *channel = ((0.5 + 0.5 * angle.cos()) * 255.0) as u8;
+    }
+
+    image::Rgb(channels)
+}
+
+/// Linearly interpolates between the colors of a ramp, the first one at a
+/// ratio of 0 and the last one at 1.
+fn get_gradient_pixel(ratio: f32, gradient: &[image::Rgb<u8>]) -> image::Rgb<u8> {
+    if gradient.len() == 1 {
+        return gradient[0];
+    }
+
+    let position = ratio.clamp(0.0, 1.0) * (gradient.len() - 1) as f32;
+    let index = (position as usize).min(gradient.len() - 2);
+    let t = position - index as f32;
+
+    let (from, to) = (gradient[index].0, gradient[index + 1].0);
+    image::Rgb([0, 1, 2].map(|i| (from[i] as f32 + (to[i] as f32 - from[i] as f32) * t).round() as u8))
+}
+
+pub fn escape(c: Complex<f32>, params: &RenderParams) -> Escape {
+    escape_with_cap(c, params, params.iterations)
+}
+
+/// Like `escape`, but with an iteration depth overriding the one in `params`.
+pub fn escape_with_cap(c: Complex<f32>, params: &RenderParams, cap: u32) -> Escape {
+    let (in_set, iterations, z) = match params.fractal {
+        Fractal::Mandelbrot => in_mandelbrot_set(c, cap, params.bailout, params.bailout_shape, params.power),
+        Fractal::Julia => in_julia_set(c, params.julia, cap, params.bailout, params.bailout_shape, params.power),
+        Fractal::BurningShip => iterate(&BurningShip { power: params.power }, c, c, cap, params.bailout, params.bailout_shape),
+        Fractal::Tricorn => iterate(&Tricorn { power: params.power }, c, c, cap, params.bailout, params.bailout_shape),
+        Fractal::Newton => {
+            // A separate Newton cap takes the place of the iteration depth.
+            let cap = params.newton_iterations.unwrap_or(cap);
+            let (converged, iterations, z) = newton_converges(c, cap, params.convergence_eps);
+            (!converged, iterations, z)
+        }
+    };
+
+    with_channel(Escape { in_set, iterations, z, channel: 0.0 }, c, params)
+}
+
+/// Fills in `Escape::channel` for escaped points. Channels needing the orbit
+/// iterate it once more, which leaves the escape loop itself untouched.
+fn with_channel(escape: Escape, c: Complex<f32>, params: &RenderParams) -> Escape {
+    match params.index_channel {
+        IndexChannel::Distance | IndexChannel::OrbitTrap if !escape.in_set => {
+            Escape { channel: channel_value(c, params, escape.iterations), ..escape }
+        },
+        _ => escape
+    }
+}
+
+/// Newton basins are colored by the root they converge to, darkened the more
+/// iterations that took. Points that never converge are black.
+fn newton_color(escape: &Escape, params: &RenderParams) -> image::Rgb<u8> {
+    if escape.in_set {
+        return image::Rgb([0, 0, 0]);
+    }
+
+    let brightness = 1.0 - escape.iterations as f32 / params.newton_iterations().max(1) as f32;
+
+    if params.color {
+        let image::Rgb([r, g, b]) = get_hue_pixel(escape.z.arg() / (2.0 * std::f32::consts::PI) + 0.5);
+        image::Rgb([r, g, b].map(|channel| (channel as f32 * brightness) as u8))
+    } else {
+        get_greyscale_pixel(brightness)
+    }
+}
+
+/// Like `escape_with_cap`, but skips iterating points that are known to be
+/// inside the set and stops orbits once they turn out to be periodic. The
+/// results match `escape_with_cap` except for the final `z` of points inside
+/// the set, so this only suits coloring that does not look at those.
+pub fn escape_fast(c: Complex<f32>, params: &RenderParams, cap: u32) -> Escape {
+    let quadratic = params.power == 2 && params.bailout_shape == BailoutShape::Circle;
+    // Orbits inside the cardioid and the bulb stay within a radius of 2, but
+    // smaller bailouts let some of them escape.
+    let interior = params.power == 2 && params.bailout_shape.contains_disc(params.bailout, 2.0);
+
+    let (in_set, iterations, z) = match params.fractal {
+        Fractal::Mandelbrot if interior && in_cardioid_or_bulb(c) => (true, cap, c),
+        Fractal::Mandelbrot if quadratic => iterate_quadratic(c, c, cap, params.bailout, true),
+        Fractal::Julia if quadratic => iterate_quadratic(c, params.julia, cap, params.bailout, true),
+        Fractal::Mandelbrot => iterate_periodic(&Multibrot { power: params.power }, c, c, cap, params.bailout, params.bailout_shape),
+        Fractal::Julia => iterate_periodic(&Multibrot { power: params.power }, c, params.julia, cap, params.bailout, params.bailout_shape),
+        Fractal::BurningShip => iterate_periodic(&BurningShip { power: params.power }, c, c, cap, params.bailout, params.bailout_shape),
+        Fractal::Tricorn => iterate_periodic(&Tricorn { power: params.power }, c, c, cap, params.bailout, params.bailout_shape),
+        Fractal::Newton => return escape_with_cap(c, params, cap)
+    };
+
+    with_channel(Escape { in_set, iterations, z, channel: 0.0 }, c, params)
+}
+
+pub fn get_mandelbrot_color(c: Complex<f32>, params: &RenderParams) -> image::Rgb<u8> {
+    escape_color(&escape(c, params), params)
+}
+
+pub fn escape_color(escape: &Escape, params: &RenderParams) -> image::Rgb<u8> {
+    if params.fractal == Fractal::Newton {
+        return newton_color(escape, params);
+    }
+
+    let Escape { in_set, iterations: iterations_taken, z, channel } = *escape;
 
     if in_set {
-        image::Rgb([0, 0, 0])
+        match params.interior {
+            InteriorMode::Solid => image::Rgb([0, 0, 0]),
+            InteriorMode::Angle => get_hue_pixel(z.arg() / (2.0 * std::f32::consts::PI) + 0.5)
+        }
     } else {
-        if color {
-            get_color_pixel(iterations_taken as f32 / iterations as f32)
+        let escape_count = if params.potential || params.index_channel == IndexChannel::Potential {
+            // Scaled so that a potential reaching the iteration depth maps
+            // onto the same ratio as the plain escape count would.
+            let count = -log_potential(iterations_taken, z, params.power) / (params.power as f32).ln();
+
+            match params.equipotential_bands {
+                0 => count,
+                bands => {
+                    let band = params.iterations as f32 / bands as f32;
+                    (count / band).floor() * band
+                }
+            }
+        } else if params.smooth {
+            // The strength blends between the integer bands and the fully
+            // smooth gradient.
+            let smooth = smooth_iterations(iterations_taken, z, params.bailout, params.power);
+            iterations_taken as f32 + params.smooth_strength * (smooth - iterations_taken as f32)
         } else {
-            get_greyscale_pixel(iterations_taken as f32 / iterations as f32)
+            iterations_taken as f32
+        };
+
+        let index = match params.index_channel {
+            // Distances are measured in pixels, so that the palette runs from
+            // the boundary outwards the same way at any zoom.
+            IndexChannel::Distance => 1.0 / (1.0 + (1.0 + channel * params.width as f32 / params.radius.0).ln()),
+            IndexChannel::OrbitTrap => (channel / params.bailout).min(1.0),
+            IndexChannel::Iteration | IndexChannel::Potential => escape_count / params.iterations as f32
+        };
+
+        let mut ratio = params.tonemap.apply(index, params.exposure);
+
+        if params.color_cycles > 1 {
+            ratio = (ratio * params.color_cycles as f32).fract();
+        }
+
+        if params.palette_shift != 0.0 {
+            ratio = (ratio + params.palette_shift).rem_euclid(1.0);
+        }
+
+        if params.color {
+            match (&params.gradient, &params.sine_palette) {
+                (Some(gradient), _) if !gradient.is_empty() => get_gradient_pixel(ratio, gradient),
+                (_, Some(palette)) => get_sine_pixel(ratio, palette),
+                _ => get_color_pixel(ratio)
+            }
+        } else {
+            get_greyscale_pixel(ratio)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Points on a grid over the whole set and its surroundings.
+    fn grid_points() -> impl Iterator<Item = Complex<f32>> {
+        (0..48).flat_map(|y| (0..64).map(move |x| Complex::new(-2.2 + x as f32 * 0.05, -1.2 + y as f32 * 0.05)))
+    }
+
+    /// The plain escape loop, squaring `z` with a full complex multiplication.
+    fn reference_escape(start: Complex<f32>, c: Complex<f32>, iterations: u32, bailout: f32) -> (bool, u32, Complex<f32>) {
+        let mut z = start;
+
+        for i in 0..iterations {
+            if z.re.powi(2) + z.im.powi(2) > bailout * bailout {
+                return (false, i, z);
+            }
+
+            z = z * z + c;
+        }
+
+        (true, iterations, z)
+    }
+
+    #[test]
+    fn quadratic_iteration_matches_the_plain_loop() {
+        let k = Complex::new(-0.8, 0.156);
+
+        for c in grid_points() {
+            assert_eq!(in_mandelbrot_set(c, 200, 2.0, BailoutShape::Circle, 2), reference_escape(c, c, 200, 2.0), "c = {}", c);
+            assert_eq!(in_julia_set(c, k, 200, 2.0, BailoutShape::Circle, 2), reference_escape(c, k, 200, 2.0), "z = {}", c);
+        }
+    }
+
+    #[test]
+    fn angle_interior_colors_points_by_their_final_angle() {
+        let params = RenderParams { interior: InteriorMode::Angle, ..crate::RenderBuilder::new().build().unwrap() };
+        let (a, b) = (escape(Complex::new(-0.1, 0.1), &params), escape(Complex::new(-1.0, 0.05), &params));
+
+        assert!(a.in_set && b.in_set);
+        assert_ne!(a.z.arg(), b.z.arg());
+        assert_ne!(escape_color(&a, &params), escape_color(&b, &params));
+
+        let solid = RenderParams { interior: InteriorMode::Solid, ..params };
+        assert_eq!(escape_color(&a, &solid), image::Rgb([0, 0, 0]));
+    }
+
+    /// Largest difference between the smooth counts of neighbouring points
+    /// on the real axis from 0.5 to 1.5, which all escape.
+    fn largest_step(smooth: impl Fn(u32, Complex<f32>) -> f32, bailout: f32, power: u32) -> f32 {
+        let values: Vec<f32> = (0..2000).map(|i| {
+            let c = Complex::new(0.5 + i as f32 * 0.0005, 0.0);
+            let (in_set, iterations, z) = in_mandelbrot_set(c, 100, bailout, BailoutShape::Circle, power);
+
+            assert!(!in_set);
+            smooth(iterations, z)
+        }).collect();
+
+        values.windows(2).map(|pair| (pair[1] - pair[0]).abs()).fold(0.0, f32::max)
+    }
+
+    #[test]
+    fn smooth_counts_stay_continuous_for_other_bailouts_and_powers() {
+        let (bailout, power) = (10.0, 3);
+        let assuming_defaults = |iterations: u32, z: Complex<f32>| iterations as f32 + 1.0 - z.norm().ln().log2();
+
+        assert!(largest_step(|iterations, z| smooth_iterations(iterations, z, bailout, power), bailout, power) < 0.1);
+        assert!(largest_step(assuming_defaults, bailout, power) > 0.4);
+    }
+
+    #[test]
+    fn adjacent_ratios_give_adjacent_colors() {
+        let steps = 1000;
+
+        for step in 0..steps {
+            let (a, b) = (get_color_pixel(step as f32 / steps as f32), get_color_pixel((step + 1) as f32 / steps as f32));
+            let delta = a.0.iter().zip(b.0).map(|(&a, b)| a.abs_diff(b)).max().unwrap();
+
+            assert!(delta <= 8, "ratio {} jumps by {}", step as f32 / steps as f32, delta);
+        }
+    }
+
+    #[test]
+    fn tighter_newton_epsilon_takes_more_iterations() {
+        // The cube roots of unity have their basin boundaries along these rays.
+        let boundary_points: Vec<Complex<f32>> = (1..40).flat_map(|step| {
+            let distance = step as f32 * 0.05;
+            [Complex::from_polar(distance, std::f32::consts::PI), Complex::from_polar(distance, std::f32::consts::PI / 3.0)]
+        }).collect();
+
+        let counts = |epsilon| -> Vec<u32> {
+            boundary_points.iter().map(|&c| newton_converges(c, 200, epsilon).1).collect()
+        };
+        let (loose, tight) = (counts(1e-1), counts(1e-5));
+
+        assert!(loose.iter().zip(&tight).all(|(loose, tight)| loose <= tight));
+        assert!(tight.iter().sum::<u32>() > loose.iter().sum::<u32>());
+    }
+
+    #[test]
+    fn newton_iterations_cap_newton_orbits_in_place_of_the_depth() {
+        let c = Complex::from_polar(0.8, std::f32::consts::PI);
+        let builder = || crate::RenderBuilder::new().fractal(Fractal::Newton).convergence_eps(1e-5);
+
+        let separate = builder().iterations(4).newton_iterations(Some(200)).build().unwrap();
+        let depth = builder().iterations(200).build().unwrap();
+        let escape = escape(c, &separate);
+
+        assert_eq!((escape.in_set, escape.iterations), (false, newton_converges(c, 200, 1e-5).1));
+        assert!(escape.iterations > 4, "{}", escape.iterations);
+        assert_eq!(escape_color(&escape, &separate), escape_color(&escape, &depth));
+        assert_ne!(escape_color(&escape, &separate), escape_color(&escape, &builder().iterations(50).build().unwrap()));
+    }
+
+    #[test]
+    fn smooth_strength_blends_between_bands_and_the_full_gradient() {
+        let image = |smooth: bool, strength: f32| {
+            let params = crate::RenderBuilder::new().dimensions(40, 30).iterations(64).color(true)
+                .smooth(smooth).smooth_strength(strength).build().unwrap();
+            crate::render::render(&params, |_| {}).unwrap()
+        };
+
+        let (bands, full) = (image(false, 1.0), image(true, 1.0));
+
+        assert_ne!(bands, full);
+        assert_eq!(image(true, 0.0), bands);
+        assert!(image(true, 0.5) != bands && image(true, 0.5) != full);
+    }
+
+    #[test]
+    fn sine_channels_cycle_at_their_frequencies() {
+        let palette = SinePalette { frequencies: [1.0, 2.0, 4.0], phases: [0.0; 3] };
+
+        assert_eq!(get_sine_pixel(0.0, &palette), image::Rgb([255, 255, 255]));
+        assert_eq!(get_sine_pixel(0.25, &palette), image::Rgb([127, 0, 255]));
+        assert_eq!(get_sine_pixel(0.5, &palette), image::Rgb([0, 255, 255]));
+
+        // Every channel rises from dark to bright once per cycle of its own.
+        for (channel, frequency) in [1, 2, 4].into_iter().enumerate() {
+            let mut dark = false;
+            let mut rises = 0;
+
+            for step in 0..=400 {
+                let value = get_sine_pixel(step as f32 / 400.0, &palette)[channel];
+
+                if value < 64 {
+                    dark = true;
+                } else if value > 192 && dark {
+                    dark = false;
+                    rises += 1;
+                }
+            }
+
+            assert_eq!(rises, frequency, "channel {}", channel);
+        }
+
+        let shifted = SinePalette { frequencies: [1.0; 3], phases: [0.5, 0.0, 0.0] };
+        assert_eq!(get_sine_pixel(0.0, &shifted), image::Rgb([0, 255, 255]));
+    }
+
+    #[test]
+    fn potential_falls_towards_the_boundary_whatever_the_bailout() {
+        let potential_at = |re: f32, bailout: f32| {
+            let (in_set, iterations, z) = in_mandelbrot_set(Complex::new(re, 0.0), 1000, bailout, BailoutShape::Circle, 2);
+            assert!(!in_set);
+
+            potential(iterations, z, 2)
+        };
+
+        // The set meets the positive real axis at 0.25.
+        let potentials: Vec<f32> = (1..60).map(|step| potential_at(0.25 + step as f32 * 0.05, 1000.0)).collect();
+
+        assert!(potentials.windows(2).all(|pair| pair[0] < pair[1]), "{:?}", potentials);
+        assert!(potentials[0] > 0.0 && potentials[0] < 0.01);
+
+        for re in [0.3, 1.0, 2.5] {
+            assert!((potential_at(re, 1000.0) - potential_at(re, 1e5)).abs() < 1e-4, "at {}", re);
         }
     }
+
+    #[test]
+    fn formulas_escape_where_expected() {
+        fn check<F: FractalFormula>(formula: F, cases: &[((f32, f32), Option<u32>)]) {
+            for &((re, im), escapes_at) in cases {
+                let (in_set, iterations, _) = iterate(&formula, Complex::new(0.0, 0.0), Complex::new(re, im), 200, 2.0, BailoutShape::Circle);
+                let expected = escapes_at.map_or((true, 200), |at| (false, at));
+
+                assert_eq!((in_set, iterations), expected, "c = {} + {}i", re, im);
+            }
+        }
+
+        check(Multibrot { power: 2 }, &[((0.0, 0.0), None), ((-1.0, 0.0), None), ((-0.5, 0.5), None), ((0.3, 0.0), Some(12))]);
+        check(Multibrot { power: 3 }, &[((0.3, 0.0), None), ((-1.0, 0.0), Some(3)), ((-0.6, 0.0), Some(4))]);
+
+        // Unlike the others the burning ship is not symmetric about the real axis.
+        check(BurningShip { power: 2 }, &[((-1.0, 0.0), None), ((-0.5, -0.5), None), ((-0.5, 0.5), Some(4))]);
+        check(Tricorn { power: 2 }, &[((-1.0, 0.0), None), ((-0.5, -0.5), Some(4)), ((-0.2, -0.5), Some(5))]);
+    }
+
+    #[test]
+    fn bailout_shapes_escape_just_beyond_their_boundary() {
+        let shapes = [BailoutShape::Circle, BailoutShape::Square, BailoutShape::Diamond];
+        // Points on the diagonal, where |z| = 2 lies at 1.414, the square's corner
+        // at 2 and the diamond's edge at 1.
+        let boundaries = [2.0f32.sqrt(), 2.0, 1.0];
+
+        for (shape, boundary) in shapes.into_iter().zip(boundaries) {
+            assert!(!shape.escaped(Complex::new(boundary - 0.01, boundary - 0.01), 2.0), "{:?}", shape);
+            assert!(shape.escaped(Complex::new(boundary + 0.01, boundary + 0.01), 2.0), "{:?}", shape);
+
+            // Along the axes all of them reach out to the bailout.
+            assert!(!shape.escaped(Complex::new(1.99, 0.0), 2.0) && shape.escaped(Complex::new(0.0, -2.01), 2.0), "{:?}", shape);
+        }
+
+        // Near the threshold the shapes change which iteration a point escapes on.
+        let counts = |re, im| shapes.map(|shape| in_mandelbrot_set(Complex::new(re, im), 100, 2.0, shape, 2).1);
+        assert_eq!(counts(0.4, 0.6), [14, 15, 14]);
+        assert_eq!(counts(-0.1, 1.1), [4, 4, 1]);
+    }
+
+    #[test]
+    fn tonemapping_keeps_dim_and_bright_regions_apart() {
+        let exposure = 8.0;
+
+        // Raising the exposure clips everything from 1/8 up under the linear curve.
+        assert_eq!(Tonemap::Linear.apply(0.5, exposure), Tonemap::Linear.apply(0.9, exposure));
+
+        for tonemap in [Tonemap::Reinhard, Tonemap::Aces] {
+            let levels = [0.005, 0.01, 0.5, 0.9].map(|ratio| tonemap.apply(ratio, exposure));
+
+            assert!(levels.windows(2).all(|pair| pair[0] < pair[1]), "{:?}: {:?}", tonemap, levels);
+            // Still a visible step once quantized to 8 bits.
+            assert!(levels[1] - levels[0] > 1.0 / 255.0 && levels[3] - levels[2] > 1.0 / 255.0, "{:?}: {:?}", tonemap, levels);
+            assert!((tonemap.apply(1.0, exposure) - 1.0).abs() < 1e-5);
+        }
+    }
+
+    #[test]
+    fn index_channels_change_the_colors_but_not_the_iterations() {
+        let channels = [IndexChannel::Iteration, IndexChannel::Potential, IndexChannel::Distance, IndexChannel::OrbitTrap];
+        let renders: Vec<_> = channels.iter().map(|&channel| {
+            let params = crate::RenderBuilder::new().dimensions(40, 30).iterations(64).color(true).index_channel(channel).build().unwrap();
+            let grid = crate::render::compute_grid(&params, |_| {}).unwrap();
+            let imgbuf = crate::render::colorize(&grid, &params);
+
+            (grid, imgbuf)
+        }).collect();
+
+        for (index, (grid, imgbuf)) in renders.iter().enumerate().skip(1) {
+            assert_eq!((&grid.in_set, &grid.iterations), (&renders[0].0.in_set, &renders[0].0.iterations), "{:?}", channels[index]);
+            assert!(renders[..index].iter().all(|(_, other)| other != imgbuf), "{:?}", channels[index]);
+        }
+    }
+
+    #[test]
+    fn squared_magnitude_bailout_matches_num_pow() {
+        for power in [2, 3, 4] {
+            for c in grid_points() {
+                let mut z = c;
+                let mut expected = (true, 200);
+
+                for i in 0..200 {
+                    if num::pow(z.re, 2) + num::pow(z.im, 2) > 4.0 {
+                        expected = (false, i);
+                        break;
+                    }
+
+                    z = z.powu(power) + c;
+                }
+
+                let (in_set, iterations, _) = in_mandelbrot_set(c, 200, 2.0, BailoutShape::Circle, power);
+                assert_eq!((in_set, iterations), expected, "c = {} at power {}", c, power);
+            }
+        }
+    }
+
+    #[test]
+    fn color_cycles_repeat_the_palette() {
+        let color = |cycles: u32, iterations: u32| {
+            let params = crate::RenderBuilder::new().iterations(64).color(true).color_cycles(cycles).build().unwrap();
+            escape_color(&Escape { in_set: false, iterations, z: Complex::new(3.0, 0.0), channel: 0.0 }, &params)
+        };
+
+        // Ratios of 0.25 and 0.75 with two cycles land where 0.5 does with one.
+        assert_eq!(color(2, 16), color(1, 32));
+        assert_eq!(color(2, 48), color(1, 32));
+        assert_eq!(color(3, 16), color(1, 48));
+        assert_ne!(color(2, 16), color(1, 16));
+    }
 }
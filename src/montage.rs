@@ -0,0 +1,94 @@
+use num::complex::Complex;
+
+use crate::error::MandelbrotError;
+use crate::mandelbrot::Fractal;
+use crate::render::{render, RenderParams};
+use crate::util;
+
+/// Height of the view onto the Julia set. Connected Julia sets lie within
+/// `|z| <= 2`, so this shows all of them with a little margin.
+const JULIA_EXTENT: f32 = 4.0;
+const MARKER_SIZE: i64 = 4;
+
+/// Widths of the Mandelbrot and Julia halves of a split image.
+pub fn split_widths(width: u32, ratio: f32, gap: u32) -> Result<(u32, u32), MandelbrotError> {
+    if !(ratio > 0.0 && ratio < 1.0) {
+        return Err(MandelbrotError::InvalidParameter(format!("split ratio must lie in (0, 1), got {}", ratio)));
+    }
+
+    let available = width.saturating_sub(gap);
+    let left = (available as f32 * ratio).round() as u32;
+
+    if left == 0 || left >= available {
+        return Err(MandelbrotError::InvalidParameter(
+            format!("a {} pixel wide image is too narrow to split with a {} pixel gap", width, gap)
+        ));
+    }
+
+    Ok((left, available - left))
+}
+
+/// Renders the Mandelbrot set view of `params` on the left and the Julia set
+/// for the constant `params.julia` on the right, with a marker on the
+/// Mandelbrot set where that constant lies. Points of the Mandelbrot set give
+/// connected Julia sets, points outside of it dust-like ones.
+pub fn render_split(params: &RenderParams, ratio: f32, gap: u32) -> Result<image::RgbImage, MandelbrotError> {
+    let (left_width, right_width) = split_widths(params.width, ratio, gap)?;
+    let height = params.height as f32;
+
+    let mandelbrot_params = RenderParams {
+        width: left_width,
+        fractal: Fractal::Mandelbrot,
+        radius: (params.radius.1 * left_width as f32 / height, params.radius.1),
+        iteration_mask: None,
+        ..params.clone()
+    };
+
+    let julia_params = RenderParams {
+        width: right_width,
+        fractal: Fractal::Julia,
+        center: (0.0, 0.0),
+        radius: (JULIA_EXTENT * right_width as f32 / height, JULIA_EXTENT),
+        iteration_mask: None,
+        ..params.clone()
+    };
+
+    let mut mandelbrot = render(&mandelbrot_params, |_| {})?;
+    let julia = render(&julia_params, |_| {})?;
+
+    let (x, y) = mandelbrot_params.pixel(Complex::new(params.julia.re, params.julia.im));
+    util::draw_marker(&mut mandelbrot, x.floor() as i64, y.floor() as i64, MARKER_SIZE, image::Rgb([255, 255, 255]));
+
+    let mut imgbuf = image::ImageBuffer::from_pixel(params.width, params.height, image::Rgb([32, 32, 32]));
+    image::imageops::replace(&mut imgbuf, &mandelbrot, 0, 0);
+    image::imageops::replace(&mut imgbuf, &julia, (left_width + gap) as i64, 0);
+
+    Ok(imgbuf)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn split_shows_both_sets_and_marks_the_julia_constant() {
+        let params = crate::RenderBuilder::new().dimensions(210, 100).center(-0.5, 0.0).radius(2.0).julia(-0.5, 0.5)
+            .iterations(64).color(true).build().unwrap();
+        let imgbuf = render_split(&params, 0.5, 10).unwrap();
+        let white = image::Rgb([255, 255, 255]);
+
+        assert_eq!(imgbuf.dimensions(), (210, 100));
+
+        // Each half is 100 pixels wide, 50 pixels per unit.
+        for (start, end) in [(0, 100), (110, 210)] {
+            let first = *imgbuf.get_pixel(start, 0);
+            assert!((start..end).any(|x| (0..100).any(|y| *imgbuf.get_pixel(x, y) != first)), "half starting at {}", start);
+        }
+
+        assert!((100..110).all(|x| *imgbuf.get_pixel(x, 50) == image::Rgb([32, 32, 32])));
+        assert_eq!(*imgbuf.get_pixel(50, 25), white);
+        assert_eq!(*imgbuf.get_pixel(50 + MARKER_SIZE as u32, 25), white);
+        assert_eq!(*imgbuf.get_pixel(50, 25 - MARKER_SIZE as u32), white);
+        assert_ne!(*imgbuf.get_pixel(51, 26), white);
+    }
+}
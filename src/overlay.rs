@@ -0,0 +1,184 @@
+use crate::font;
+use crate::render::RenderParams;
+
+fn label_decimals(spacing: f32) -> usize {
+    (-spacing.log10()).ceil().max(0.0) as usize
+}
+
+fn grid_values(from: f32, to: f32, spacing: f32) -> Vec<(i64, f32)> {
+    let (low, high) = if from <= to { (from, to) } else { (to, from) };
+
+    ((low / spacing).ceil() as i64..=(high / spacing).floor() as i64)
+        .map(|k| (k, k as f32 * spacing))
+        .collect()
+}
+
+/// Overlays the real and imaginary axes along with gridlines every `spacing`
+/// units of the complex plane, labelling each line with its value. Gridlines
+/// are left out if they would end up closer than a few pixels to each other.
+pub fn draw_axes(imgbuf: &mut image::RgbImage, params: &RenderParams, spacing: f32, axis_color: image::Rgb<u8>, grid_color: image::Rgb<u8>) {
+    let (width, height) = imgbuf.dimensions();
+    let top_left = params.point(0.0, 0.0);
+    let bottom_right = params.point(width as f32, height as f32);
+    let (origin_x, origin_y) = params.pixel(num::complex::Complex::new(0.0, 0.0));
+
+    let draw_grid = spacing > 0.0 && spacing / params.radius.0 * width as f32 >= 4.0
+        && spacing / params.radius.1 * height as f32 >= 4.0;
+
+    let decimals = label_decimals(spacing);
+    let label_y = (origin_y.floor() as i64 + 2).clamp(0, (height as i64 - font::text_height(1) as i64).max(0));
+    let label_x = (origin_x.floor() as i64 + 2).clamp(0, width as i64 - 1);
+
+    let (real_lines, imaginary_lines) = if draw_grid {
+        (grid_values(top_left.re, bottom_right.re, spacing), grid_values(bottom_right.im, top_left.im, spacing))
+    } else {
+        (vec![(0, 0.0)], vec![(0, 0.0)])
+    };
+
+    let real_lines: Vec<(i64, f32)> = real_lines.into_iter().filter(|&(k, _)| k != 0).map(|(_, value)| {
+        (params.pixel(num::complex::Complex::new(value, 0.0)).0.floor() as i64, value)
+    }).collect();
+
+    let imaginary_lines: Vec<(i64, f32)> = imaginary_lines.into_iter().filter(|&(k, _)| k != 0).map(|(_, value)| {
+        (params.pixel(num::complex::Complex::new(0.0, value)).1.floor() as i64, value)
+    }).collect();
+
+    for &(x, _) in &real_lines {
+        draw_vertical(imgbuf, x, grid_color);
+    }
+
+    for &(y, _) in &imaginary_lines {
+        draw_horizontal(imgbuf, y, grid_color);
+    }
+
+    draw_vertical(imgbuf, origin_x.floor() as i64, axis_color);
+    draw_horizontal(imgbuf, origin_y.floor() as i64, axis_color);
+
+    for &(x, value) in &real_lines {
+        font::draw_text(imgbuf, x + 2, label_y, &format!("{:.*}", decimals, value), axis_color, 1);
+    }
+
+    for &(y, value) in &imaginary_lines {
+        font::draw_text(imgbuf, label_x, y + 2, &format!("{:.*}i", decimals, value), axis_color, 1);
+    }
+}
+
+fn draw_vertical(imgbuf: &mut image::RgbImage, x: i64, color: image::Rgb<u8>) {
+    let (width, height) = imgbuf.dimensions();
+
+    if x >= 0 && x < width as i64 {
+        for y in 0..height {
+            imgbuf.put_pixel(x as u32, y, color);
+        }
+    }
+}
+
+fn draw_horizontal(imgbuf: &mut image::RgbImage, y: i64, color: image::Rgb<u8>) {
+    let (width, height) = imgbuf.dimensions();
+
+    if y >= 0 && y < height as i64 {
+        for x in 0..width {
+            imgbuf.put_pixel(x, y as u32, color);
+        }
+    }
+}
+
+/// Where `draw_caption` places the caption.
+#[derive(clap::ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum CaptionPosition {
+    TopLeft,
+    TopRight,
+    BottomLeft,
+    BottomRight
+}
+
+/// Appearance of a caption: glyph scale, text color and a box blended in
+/// behind the text with the given opacity, which keeps it legible over busy
+/// parts of the image.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct CaptionStyle {
+    pub scale: u32,
+    pub color: image::Rgb<u8>,
+    pub box_color: image::Rgb<u8>,
+    pub box_opacity: f32
+}
+
+const CAPTION_MARGIN: i64 = 4;
+const CAPTION_PADDING: i64 = 3;
+
+/// Draws `text` into a corner of the image on top of a backing box.
+pub fn draw_caption(imgbuf: &mut image::RgbImage, text: &str, position: CaptionPosition, style: &CaptionStyle) {
+    let (width, height) = imgbuf.dimensions();
+    let text_width = font::text_width(text, style.scale) as i64;
+    let text_height = font::text_height(style.scale) as i64;
+
+    let inset = CAPTION_MARGIN + CAPTION_PADDING;
+    let x = match position {
+        CaptionPosition::TopLeft | CaptionPosition::BottomLeft => inset,
+        CaptionPosition::TopRight | CaptionPosition::BottomRight => width as i64 - inset - text_width
+    };
+    let y = match position {
+        CaptionPosition::TopLeft | CaptionPosition::TopRight => inset,
+        CaptionPosition::BottomLeft | CaptionPosition::BottomRight => height as i64 - inset - text_height
+    };
+
+    let opacity = style.box_opacity.clamp(0.0, 1.0);
+
+    for py in (y - CAPTION_PADDING).max(0)..(y + text_height + CAPTION_PADDING).min(height as i64) {
+        for px in (x - CAPTION_PADDING).max(0)..(x + text_width + CAPTION_PADDING).min(width as i64) {
+            let pixel = imgbuf.get_pixel_mut(px as u32, py as u32);
+
+            for (channel, box_channel) in pixel.0.iter_mut().zip(style.box_color.0) {
+                *channel = (*channel as f32 * (1.0 - opacity) + box_channel as f32 * opacity).round() as u8;
+            }
+        }
+    }
+
+    font::draw_text(imgbuf, x, y, text, style.color, style.scale);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn gridlines_fall_on_their_complex_coordinates() {
+        let params = crate::RenderBuilder::new().dimensions(200, 200).center(0.0, 0.0).radius(4.0).build().unwrap();
+        let (axis, grid) = (image::Rgb([255, 0, 0]), image::Rgb([0, 0, 255]));
+        let mut imgbuf = image::RgbImage::new(200, 200);
+
+        draw_axes(&mut imgbuf, &params, 1.0, axis, grid);
+
+        // The view spans -2 to 2 on both axes, 50 pixels per unit.
+        assert_eq!(*imgbuf.get_pixel(100, 10), axis);
+        assert_eq!(*imgbuf.get_pixel(10, 100), axis);
+        assert_eq!(*imgbuf.get_pixel(150, 10), grid);
+        assert_eq!(*imgbuf.get_pixel(50, 190), grid);
+        assert_eq!(*imgbuf.get_pixel(10, 50), grid);
+        assert_eq!(*imgbuf.get_pixel(125, 10), image::Rgb([0, 0, 0]));
+    }
+
+    #[test]
+    fn captions_are_drawn_into_their_corner() {
+        let style = CaptionStyle { scale: 1, color: image::Rgb([255, 255, 0]), box_color: image::Rgb([255, 255, 255]), box_opacity: 0.5 };
+        let (text_width, text_height) = (font::text_width("c = -0.75", 1), font::text_height(1));
+        let inset = (CAPTION_MARGIN + CAPTION_PADDING) as u32;
+
+        for position in [CaptionPosition::TopLeft, CaptionPosition::BottomRight] {
+            let mut imgbuf = image::RgbImage::new(120, 60);
+            draw_caption(&mut imgbuf, "c = -0.75", position, &style);
+
+            let (x, y) = match position {
+                CaptionPosition::TopLeft => (inset, inset),
+                _ => (120 - inset - text_width, 60 - inset - text_height)
+            };
+
+            let text = (y..y + text_height).flat_map(|py| (x..x + text_width).map(move |px| (px, py)))
+                .filter(|&(px, py)| *imgbuf.get_pixel(px, py) == style.color).count();
+
+            assert!(text > 0, "{:?}", position);
+            assert_eq!(*imgbuf.get_pixel(x - 2, y - 2), image::Rgb([128, 128, 128]), "{:?}", position);
+            assert_eq!(*imgbuf.get_pixel(119 - x, 59 - y), image::Rgb([0, 0, 0]), "{:?}", position);
+        }
+    }
+}
@@ -0,0 +1,112 @@
+use crate::error::MandelbrotError;
+use crate::font;
+use crate::render::{render, RenderParams};
+
+pub struct Preset {
+    pub name: &'static str,
+    pub center: (f32, f32),
+    pub radius: f32,
+    pub iterations: u32
+}
+
+pub const PRESETS: &[Preset] = &[
+    Preset { name: "default", center: (-0.75, 0.3), radius: 0.5, iterations: 32 },
+    Preset { name: "full-set", center: (-0.75, 0.0), radius: 3.0, iterations: 64 },
+    Preset { name: "seahorse-valley", center: (-0.75, 0.1), radius: 0.05, iterations: 256 },
+    Preset { name: "elephant-valley", center: (0.275, 0.0), radius: 0.05, iterations: 256 },
+    Preset { name: "triple-spiral", center: (-0.088, 0.654), radius: 0.02, iterations: 512 },
+    Preset { name: "mini-mandelbrot", center: (-1.7549, 0.0), radius: 0.04, iterations: 256 }
+];
+
+pub fn find_preset(name: &str) -> Result<&'static Preset, MandelbrotError> {
+    PRESETS.iter().find(|preset| preset.name == name).ok_or_else(|| {
+        let names: Vec<&str> = PRESETS.iter().map(|preset| preset.name).collect();
+
+        MandelbrotError::InvalidParameter(
+            format!("unknown preset '{}', available presets are: {}", name, names.join(", "))
+        )
+    })
+}
+
+impl Preset {
+    pub fn apply(&self, params: &RenderParams) -> RenderParams {
+        RenderParams {
+            center: self.center,
+            radius: (self.radius, self.radius),
+            iterations: self.iterations,
+            ..params.clone()
+        }
+    }
+}
+
+const SHEET_GAP: u32 = 8;
+const LABEL_SCALE: u32 = 1;
+
+/// Dimensions of a contact sheet holding `count` thumbnails of the given size.
+pub fn contact_sheet_dimensions(count: u32, size: u32) -> (u32, u32, u32) {
+    let columns = (count as f32).sqrt().ceil().max(1.0) as u32;
+    let rows = count.div_ceil(columns);
+    let cell_height = size + SHEET_GAP + font::text_height(LABEL_SCALE);
+
+    (
+        columns,
+        columns * (size + SHEET_GAP) + SHEET_GAP,
+        rows * (cell_height + SHEET_GAP) + SHEET_GAP
+    )
+}
+
+/// Renders every built-in preset as a square thumbnail and tiles them into a
+/// single labelled grid, using `params` for everything but the viewport.
+pub fn render_contact_sheet(params: &RenderParams, size: u32) -> Result<image::RgbImage, MandelbrotError> {
+    let (columns, width, height) = contact_sheet_dimensions(PRESETS.len() as u32, size);
+    let cell_height = size + SHEET_GAP + font::text_height(LABEL_SCALE);
+
+    let mut sheet = image::ImageBuffer::from_pixel(width, height, image::Rgb([32, 32, 32]));
+
+    for (index, preset) in PRESETS.iter().enumerate() {
+        let thumbnail_params = RenderParams {
+            width: size,
+            height: size,
+            iteration_mask: None,
+            ..preset.apply(params)
+        };
+
+        let thumbnail = render(&thumbnail_params, |_| {})?;
+
+        let x = SHEET_GAP + (index as u32 % columns) * (size + SHEET_GAP);
+        let y = SHEET_GAP + (index as u32 / columns) * (cell_height + SHEET_GAP);
+
+        image::imageops::replace(&mut sheet, &thumbnail, x as i64, y as i64);
+
+        font::draw_text(
+            &mut sheet,
+            x as i64, (y + size + SHEET_GAP / 2) as i64,
+            preset.name, image::Rgb([230, 230, 230]), LABEL_SCALE
+        );
+    }
+
+    Ok(sheet)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::builder::RenderBuilder;
+
+    #[test]
+    fn contact_sheet_has_one_cell_per_preset() {
+        let params = RenderBuilder::new().threads(2).build().unwrap();
+        let sheet = render_contact_sheet(&params, 32).unwrap();
+
+        // Six presets fill a grid of three columns and two rows.
+        let cell_height = 32 + SHEET_GAP + font::text_height(LABEL_SCALE);
+        let (columns, width, height) = contact_sheet_dimensions(PRESETS.len() as u32, 32);
+
+        assert_eq!((columns, width, height), (3, 3 * (32 + SHEET_GAP) + SHEET_GAP, 2 * (cell_height + SHEET_GAP) + SHEET_GAP));
+        assert_eq!(sheet.dimensions(), (width, height));
+
+        // The full set thumbnail sits in the second cell of the first row.
+        let full_set = render(&RenderParams { width: 32, height: 32, ..PRESETS[1].apply(&params) }, |_| {}).unwrap();
+        assert_eq!(image::imageops::crop_imm(&sheet, 40 + SHEET_GAP, SHEET_GAP, 32, 32).to_image(), full_set);
+    }
+}
@@ -0,0 +1,166 @@
+use std::collections::VecDeque;
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::time::{Duration, Instant};
+
+use crate::error::MandelbrotError;
+
+const EMIT_INTERVAL: Duration = Duration::from_millis(250);
+
+/// How far back `RateEstimator` looks by default.
+pub const RATE_WINDOW: Duration = Duration::from_secs(5);
+
+/// Estimates the remaining time from the throughput over a recent window
+/// rather than the whole render. Regions inside the set at a low depth or far
+/// outside of it finish much faster than the boundary, so the overall average
+/// would lag behind whatever part of the image is being worked on.
+pub struct RateEstimator {
+    window: Duration,
+    samples: VecDeque<(Instant, u64)>
+}
+
+impl RateEstimator {
+    pub fn new(window: Duration) -> RateEstimator {
+        RateEstimator { window, samples: VecDeque::new() }
+    }
+
+    /// An estimator that counts from nothing done at `start`.
+    pub fn with_start(window: Duration, start: Instant) -> RateEstimator {
+        let mut estimator = RateEstimator::new(window);
+        estimator.record(start, 0);
+        estimator
+    }
+
+    /// Records that `done` units were finished in total at `at`.
+    pub fn record(&mut self, at: Instant, done: u64) {
+        self.samples.push_back((at, done));
+
+        // Keep the newest sample older than the window as the baseline.
+        while self.samples.len() > 2 && at.duration_since(self.samples[1].0) >= self.window {
+            self.samples.pop_front();
+        }
+    }
+
+    /// Units finished per second within the window, if anything was.
+    pub fn rate(&self) -> Option<f64> {
+        let (&(first_at, first_done), &(last_at, last_done)) = (self.samples.front()?, self.samples.back()?);
+        let elapsed = last_at.duration_since(first_at).as_secs_f64();
+
+        if elapsed <= 0.0 || last_done <= first_done {
+            return None;
+        }
+
+        Some((last_done - first_done) as f64 / elapsed)
+    }
+
+    /// Seconds until `total` units will be finished at the current rate.
+    pub fn eta(&self, total: u64) -> Option<f64> {
+        let done = self.samples.back()?.1;
+        Some(total.saturating_sub(done) as f64 / self.rate()?)
+    }
+}
+
+/// Writes progress as one JSON object per line, e.g.
+/// `{"done": 1200, "total": 40000, "eta_secs": 3.5}`, for wrapping programs
+/// that want to show their own progress display. Lines are throttled to a
+/// few per second, the final one always reports `done == total`.
+pub struct JsonProgress {
+    writer: BufWriter<Box<dyn Write>>,
+    total: u64,
+    done: u64,
+    rate: RateEstimator,
+    last_emit: Option<Instant>
+}
+
+impl JsonProgress {
+    /// Opens the destination, which is either a file descriptor number such
+    /// as `3` (passed down by the parent process) or a file path.
+    pub fn open(destination: &str, total: u64) -> Result<JsonProgress, MandelbrotError> {
+        let file = match destination.parse::<u32>() {
+            Ok(fd) => std::fs::OpenOptions::new().write(true).open(format!("/dev/fd/{}", fd))?,
+            Err(_) => File::create(destination)?
+        };
+
+        Ok(JsonProgress::new(Box::new(file), total))
+    }
+
+    pub fn new(writer: Box<dyn Write>, total: u64) -> JsonProgress {
+        JsonProgress {
+            writer: BufWriter::new(writer),
+            total,
+            done: 0,
+            rate: RateEstimator::with_start(RATE_WINDOW, Instant::now()),
+            last_emit: None
+        }
+    }
+
+    pub fn add(&mut self, count: u64) -> std::io::Result<()> {
+        self.done = (self.done + count).min(self.total);
+        self.rate.record(Instant::now(), self.done);
+
+        let due = match self.last_emit {
+            Some(last) => last.elapsed() >= EMIT_INTERVAL,
+            None => true
+        };
+
+        if due && self.done < self.total {
+            self.emit()?;
+        }
+
+        Ok(())
+    }
+
+    pub fn finish(&mut self) -> std::io::Result<()> {
+        self.done = self.total;
+        self.emit()
+    }
+
+    /// Writes a last line with the work actually done, for renders that
+    /// stopped before reaching the total.
+    pub fn stop(&mut self) -> std::io::Result<()> {
+        self.emit()
+    }
+
+    fn emit(&mut self) -> std::io::Result<()> {
+        let eta = if self.done == self.total { 0.0 } else { self.rate.eta(self.total).unwrap_or(0.0) };
+
+        writeln!(self.writer, "{{\"done\": {}, \"total\": {}, \"eta_secs\": {:.2}}}", self.done, self.total, eta)?;
+        self.writer.flush()?;
+
+        self.last_emit = Some(Instant::now());
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rate_follows_the_recent_window() {
+        let start = Instant::now();
+        let at = |seconds: u64| start + Duration::from_secs(seconds);
+        let mut estimator = RateEstimator::with_start(Duration::from_secs(5), start);
+
+        assert_eq!(estimator.rate(), None);
+
+        // 100 units a second for ten seconds, then 10 a second after that.
+        for second in 1..=10 {
+            estimator.record(at(second), second * 100);
+        }
+
+        assert_eq!(estimator.rate(), Some(100.0));
+        assert_eq!(estimator.eta(2000), Some(10.0));
+
+        for second in 11..=20 {
+            estimator.record(at(second), 1000 + (second - 10) * 10);
+        }
+
+        // The overall average would still be 55 a second.
+        assert_eq!(estimator.rate(), Some(10.0));
+        assert_eq!(estimator.eta(2000), Some(90.0));
+
+        estimator.record(at(30), 1100);
+        assert_eq!(estimator.rate(), None);
+    }
+}
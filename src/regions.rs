@@ -0,0 +1,93 @@
+use crate::error::MandelbrotError;
+use crate::render::Tile;
+
+/// Parses a region file. Each non-empty line that does not start with `#`
+/// holds one pixel rectangle of the full image:
+///
+/// ```text
+/// # x   y    width  height
+/// 0     0    256    256
+/// 512   256  256    128
+/// ```
+///
+/// Regions must not overlap, so that no pixel is rendered twice.
+pub fn parse_regions(input: &str) -> Result<Vec<Tile>, MandelbrotError> {
+    let mut regions: Vec<Tile> = Vec::new();
+
+    for (number, line) in input.lines().enumerate() {
+        let line = line.trim();
+
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+
+        let fields = line.split_whitespace().map(|field| {
+            field.parse::<u32>().map_err(|_| {
+                MandelbrotError::Parse(format!("'{}' on line {} of region file", field, number + 1))
+            })
+        }).collect::<Result<Vec<u32>, MandelbrotError>>()?;
+
+        let region = match fields[..] {
+            [x, y, width, height] => Tile { x, y, width, height },
+            _ => return Err(MandelbrotError::Parse(
+                format!("region on line {}, expected 4 fields but found {}", number + 1, fields.len())
+            ))
+        };
+
+        if let Some(other) = regions.iter().find(|other| overlaps(&region, other)) {
+            return Err(MandelbrotError::InvalidParameter(format!(
+                "region on line {} overlaps the {}x{} region at ({}, {})",
+                number + 1, other.width, other.height, other.x, other.y
+            )));
+        }
+
+        regions.push(region);
+    }
+
+    if regions.is_empty() {
+        return Err(MandelbrotError::InvalidParameter("region file contains no regions".to_string()));
+    }
+
+    Ok(regions)
+}
+
+fn overlaps(a: &Tile, b: &Tile) -> bool {
+    let (a_right, a_bottom) = (a.x as u64 + a.width as u64, a.y as u64 + a.height as u64);
+    let (b_right, b_bottom) = (b.x as u64 + b.width as u64, b.y as u64 + b.height as u64);
+
+    (a.x as u64) < b_right && (b.x as u64) < a_right && (a.y as u64) < b_bottom && (b.y as u64) < a_bottom
+}
+
+/// File name for the region, with its position in the full image appended.
+pub fn region_name(name: &str, region: &Tile) -> String {
+    crate::util::suffixed_name(name, &format!("_region_{}_{}", region.x, region.y))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn stitched_regions_match_a_full_render() {
+        let params = crate::RenderBuilder::new().dimensions(60, 40).iterations(64).samples(2).color(true).build().unwrap();
+        let regions = parse_regions("# x y width height\n0 0 60 13\n0 13 25 27\n\n25 13 35 27\n").unwrap();
+        let mut stitched = image::RgbImage::new(60, 40);
+
+        for region in &regions {
+            let imgbuf = crate::render::render_region(&params, region).unwrap();
+
+            assert_eq!(imgbuf.dimensions(), (region.width, region.height));
+            image::imageops::replace(&mut stitched, &imgbuf, region.x as i64, region.y as i64);
+        }
+
+        assert_eq!(stitched, crate::render::render(&params, |_| {}).unwrap());
+    }
+
+    #[test]
+    fn overlapping_regions_are_rejected() {
+        let error = parse_regions("0 0 30 30\n20 20 10 10\n").unwrap_err();
+        assert!(matches!(&error, MandelbrotError::InvalidParameter(msg) if msg == "region on line 2 overlaps the 30x30 region at (0, 0)"), "{}", error);
+
+        assert!(parse_regions("0 0 30 30\n30 0 10 10\n").is_ok());
+    }
+}
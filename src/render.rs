@@ -0,0 +1,1308 @@
+use num::complex::Complex;
+use std::sync::atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering};
+use std::sync::mpsc::sync_channel;
+use std::sync::Arc;
+use threadpool::ThreadPool;
+
+use crate::error::MandelbrotError;
+use crate::mandelbrot::{escape, escape_color, escape_fast, escape_with_cap, smooth_iterations, BailoutShape, Escape, Fractal, IndexChannel, InteriorMode, SinePalette, Tonemap};
+use crate::util::{sample_offsets, SamplePattern};
+
+#[derive(Clone, Debug)]
+pub struct RenderParams {
+    pub width: u32,
+    pub height: u32,
+    pub center: (f32, f32),
+    pub radius: (f32, f32),
+    pub mapping: Mapping,
+    pub fractal: Fractal,
+    pub iterations: u32,
+    pub iteration_mask: Option<Arc<Vec<u32>>>,
+    /// Iteration cap of the Newton fractal, which also scales its shading.
+    /// Falls back to `iterations` if `None`.
+    pub newton_iterations: Option<u32>,
+    pub convergence_eps: f32,
+    pub julia: Complex<f32>,
+    pub bailout: f32,
+    pub bailout_shape: BailoutShape,
+    pub power: u32,
+    pub threads: usize,
+    /// Number of computed tiles that may wait to be merged into the image
+    /// before workers pause, four per thread if `None`. Tiles are only
+    /// computed as workers get to them, so this bounds the memory held by
+    /// tiles in flight however large the image is.
+    pub queue_depth: Option<usize>,
+    pub tile_size: u32,
+    pub schedule: Schedule,
+    pub tile_order: TileOrder,
+    pub samples: u32,
+    pub sample_pattern: SamplePattern,
+    pub sample_offset: f32,
+    pub color: bool,
+    pub sine_palette: Option<SinePalette>,
+    pub gradient: Option<Arc<Vec<image::Rgb<u8>>>>,
+    pub smooth: bool,
+    pub smooth_strength: f32,
+    pub potential: bool,
+    pub equipotential_bands: u32,
+    pub palette_shift: f32,
+    /// Number of times the palette repeats across the range of escape ratios.
+    pub color_cycles: u32,
+    pub index_channel: IndexChannel,
+    pub tonemap: Tonemap,
+    pub exposure: f32,
+    pub interior: InteriorMode,
+    /// Allows `escape_fast` for tiles that look like they are mostly inside
+    /// the set. Only used where it leaves the output unchanged.
+    pub fast_paths: bool,
+    /// Allows mirroring centered Julia set views instead of computing both
+    /// halves, wherever the mirrored samples land on exactly negated points.
+    pub symmetry: bool,
+    /// Earlier render whose samples are reused where they coincide.
+    pub prior: Option<Arc<PriorGrid>>,
+    /// Once set, workers stop picking up new tiles.
+    pub cancel: Option<Arc<AtomicBool>>,
+    /// Counts the shortcuts taken by renders with these parameters.
+    pub stats: Option<Arc<RenderStats>>
+}
+
+/// How often a render got away with less work than iterating and coloring
+/// every sample. Shared by every render the parameters are used for.
+#[derive(Debug, Default)]
+pub struct RenderStats {
+    /// Tiles computed with the interior fast paths.
+    pub fast_path_tiles: AtomicU64,
+    /// Samples taken from a `PriorGrid`.
+    pub reused_samples: AtomicU64,
+    /// Rows `colorize` copied from the row above.
+    pub repeated_rows: AtomicU64,
+    /// Most tiles that were waiting for the merge at once, which the queue
+    /// depth bounds.
+    pub peak_queued_tiles: AtomicU64
+}
+
+/// How pixel positions map onto the complex plane.
+///
+/// `LogPolar` wraps the angle around the center along the horizontal axis and
+/// the natural logarithm of the distance from it along the vertical one, from
+/// `max` at the top to `min` at the bottom of the `log_radius` range. A range
+/// of `(-6, 1)` spans distances from about 0.0025 to 2.7, so a single image
+/// shows every scale in between, each row zoomed a little further in.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Mapping {
+    Linear,
+    LogPolar { log_radius: (f32, f32) }
+}
+
+impl RenderParams {
+    /// Maps a (possibly fractional) pixel position onto the complex plane.
+    pub fn point(&self, x: f32, y: f32) -> Complex<f32> {
+        match self.mapping {
+            Mapping::Linear => Complex::new(
+                ((x * self.radius.0 / self.width as f32) - self.radius.0 / 2.0) + self.center.0,
+                -((y * self.radius.1 / self.height as f32) - self.radius.1 / 2.0) + self.center.1
+            ),
+            Mapping::LogPolar { log_radius: (min, max) } => {
+                let angle = x / self.width as f32 * 2.0 * std::f32::consts::PI;
+                let distance = (max - y / self.height as f32 * (max - min)).exp();
+
+                Complex::from_polar(distance, angle) + Complex::new(self.center.0, self.center.1)
+            }
+        }
+    }
+
+    /// Inverse of `point`, returning the pixel position of a complex number.
+    pub fn pixel(&self, c: Complex<f32>) -> (f32, f32) {
+        match self.mapping {
+            Mapping::Linear => (
+                (c.re - self.center.0 + self.radius.0 / 2.0) * self.width as f32 / self.radius.0,
+                (self.center.1 - c.im + self.radius.1 / 2.0) * self.height as f32 / self.radius.1
+            ),
+            Mapping::LogPolar { log_radius: (min, max) } => {
+                let (distance, angle) = (c - Complex::new(self.center.0, self.center.1)).to_polar();
+
+                (
+                    angle.rem_euclid(2.0 * std::f32::consts::PI) / (2.0 * std::f32::consts::PI) * self.width as f32,
+                    (max - distance.ln()) / (max - min) * self.height as f32
+                )
+            }
+        }
+    }
+
+    /// Adds `count` to the counter `field` picks out of the stats, if any.
+    fn count(&self, field: fn(&RenderStats) -> &AtomicU64, count: u64) {
+        if let Some(stats) = &self.stats {
+            field(stats).fetch_add(count, Ordering::Relaxed);
+        }
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.cancel.as_ref().is_some_and(|cancel| cancel.load(Ordering::Relaxed))
+    }
+
+    /// Whether the fast paths may be used, which is not the case if the
+    /// final `z` of points inside the set shows up in the output.
+    pub fn fast_paths_apply(&self) -> bool {
+        self.fast_paths && self.interior == InteriorMode::Solid && self.fractal != Fractal::Newton
+    }
+
+    pub fn newton_iterations(&self) -> u32 {
+        self.newton_iterations.unwrap_or(self.iterations)
+    }
+
+    pub fn queue_depth(&self) -> usize {
+        self.queue_depth.unwrap_or(self.threads * 4)
+    }
+
+    /// Iteration depth for a pixel, taken from the iteration mask if any.
+    pub fn iteration_cap(&self, x: u32, y: u32) -> u32 {
+        match &self.iteration_mask {
+            Some(mask) => mask[y as usize * self.width as usize + x as usize],
+            None => self.iterations
+        }
+    }
+
+    pub fn validate(&self) -> Result<(), MandelbrotError> {
+        if self.width == 0 || self.height == 0 {
+            return Err(MandelbrotError::InvalidParameter(
+                format!("image dimensions must be non-zero, got {}x{}", self.width, self.height)
+            ));
+        }
+
+        if let Some(mask) = &self.iteration_mask {
+            if mask.len() != self.width as usize * self.height as usize {
+                return Err(MandelbrotError::InvalidParameter(
+                    format!("iteration mask holds {} values but the image has {}x{} pixels", mask.len(), self.width, self.height)
+                ));
+            }
+        }
+
+        if self.samples == 0 {
+            return Err(MandelbrotError::InvalidParameter("sample count must be at least 1".to_string()));
+        }
+
+        if !(0.0..1.0).contains(&self.sample_offset) {
+            return Err(MandelbrotError::InvalidParameter(
+                format!("sample offset must lie in [0, 1), got {}", self.sample_offset)
+            ));
+        }
+
+        if self.tile_size == 0 {
+            return Err(MandelbrotError::InvalidParameter("tile size must be at least 1".to_string()));
+        }
+
+        if self.threads == 0 {
+            return Err(MandelbrotError::InvalidParameter("thread count must be at least 1".to_string()));
+        }
+
+        if self.color_cycles == 0 {
+            return Err(MandelbrotError::InvalidParameter("color cycle count must be at least 1".to_string()));
+        }
+
+        if self.queue_depth == Some(0) {
+            return Err(MandelbrotError::InvalidParameter("queue depth must be at least 1".to_string()));
+        }
+
+        for radius in [self.radius.0, self.radius.1] {
+            if !(radius.is_finite() && radius > 0.0) {
+                return Err(MandelbrotError::InvalidParameter(
+                    format!("radius must be a positive number, got {}", radius)
+                ));
+            }
+        }
+
+        if let Mapping::LogPolar { log_radius: (min, max) } = self.mapping {
+            if !(min.is_finite() && max.is_finite() && min < max) {
+                return Err(MandelbrotError::InvalidParameter(
+                    format!("log radius range must be finite and increasing, got {},{}", min, max)
+                ));
+            }
+        }
+
+        if !(self.exposure.is_finite() && self.exposure > 0.0) {
+            return Err(MandelbrotError::InvalidParameter(
+                format!("exposure must be a positive number, got {}", self.exposure)
+            ));
+        }
+
+        if !(self.bailout.is_finite() && self.bailout > 1.0) {
+            return Err(MandelbrotError::InvalidParameter(
+                format!("bailout radius must be greater than 1, got {}", self.bailout)
+            ));
+        }
+
+        if !(self.convergence_eps.is_finite() && self.convergence_eps > 0.0) {
+            return Err(MandelbrotError::InvalidParameter(
+                format!("convergence epsilon must be a positive number, got {}", self.convergence_eps)
+            ));
+        }
+
+        if !(0.0..=1.0).contains(&self.smooth_strength) {
+            return Err(MandelbrotError::InvalidParameter(
+                format!("smooth strength must lie in [0, 1], got {}", self.smooth_strength)
+            ));
+        }
+
+        if self.index_channel == IndexChannel::Distance && !matches!(self.fractal, Fractal::Mandelbrot | Fractal::Julia) {
+            return Err(MandelbrotError::InvalidParameter(
+                format!("the distance index channel needs the mandelbrot or julia fractal, got {:?}", self.fractal)
+            ));
+        }
+
+        if self.power < 2 {
+            return Err(MandelbrotError::InvalidParameter(
+                format!("power must be at least 2, got {}", self.power)
+            ));
+        }
+
+        Ok(())
+    }
+}
+
+/// Escape data for every sample of every pixel, laid out row by row with the
+/// samples of each pixel stored next to each other. Every part of an `Escape`
+/// is kept in its own array, so passes that only look at e.g. the iteration
+/// counts walk tightly packed memory.
+#[derive(Clone, Debug, Default)]
+pub struct EscapeGrid {
+    pub width: u32,
+    pub height: u32,
+    pub samples: u32,
+    pub in_set: Vec<bool>,
+    pub iterations: Vec<u32>,
+    pub z: Vec<Complex<f32>>,
+    pub channel: Vec<f32>
+}
+
+impl EscapeGrid {
+    /// A grid with every sample set to `fill`.
+    pub fn filled(width: u32, height: u32, samples: u32, fill: Escape) -> EscapeGrid {
+        let count = width as usize * height as usize * samples as usize;
+
+        EscapeGrid {
+            width, height, samples,
+            in_set: vec![fill.in_set; count],
+            iterations: vec![fill.iterations; count],
+            z: vec![fill.z; count],
+            channel: vec![fill.channel; count]
+        }
+    }
+
+    /// Number of samples held.
+    pub fn len(&self) -> usize {
+        self.iterations.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.iterations.is_empty()
+    }
+
+    pub fn get(&self, index: usize) -> Escape {
+        Escape { in_set: self.in_set[index], iterations: self.iterations[index], z: self.z[index], channel: self.channel[index] }
+    }
+
+    pub fn set(&mut self, index: usize, escape: Escape) {
+        self.in_set[index] = escape.in_set;
+        self.iterations[index] = escape.iterations;
+        self.z[index] = escape.z;
+        self.channel[index] = escape.channel;
+    }
+
+    pub fn push(&mut self, escape: Escape) {
+        self.in_set.push(escape.in_set);
+        self.iterations.push(escape.iterations);
+        self.z.push(escape.z);
+        self.channel.push(escape.channel);
+    }
+
+    /// Indices of the samples of a pixel.
+    pub fn sample_range(&self, x: u32, y: u32) -> std::ops::Range<usize> {
+        let start = (y as usize * self.width as usize + x as usize) * self.samples as usize;
+        start..start + self.samples as usize
+    }
+
+    pub fn samples_at(&self, x: u32, y: u32) -> impl ExactSizeIterator<Item = Escape> + '_ {
+        self.sample_range(x, y).map(|index| self.get(index))
+    }
+
+    /// Every sample in order.
+    pub fn escapes(&self) -> impl ExactSizeIterator<Item = Escape> + '_ {
+        (0..self.len()).map(|index| self.get(index))
+    }
+
+    /// Copies all rows of `other` into this grid with its top-left pixel at
+    /// `(x, y)`. Both grids must hold the same number of samples per pixel.
+    pub fn copy_from(&mut self, other: &EscapeGrid, x: u32, y: u32) {
+        let row = other.width as usize * self.samples as usize;
+
+        for line in 0..other.height as usize {
+            let from = line * row;
+            let to = ((y as usize + line) * self.width as usize + x as usize) * self.samples as usize;
+
+            self.in_set[to..to + row].copy_from_slice(&other.in_set[from..from + row]);
+            self.iterations[to..to + row].copy_from_slice(&other.iterations[from..from + row]);
+            self.z[to..to + row].copy_from_slice(&other.z[from..from + row]);
+            self.channel[to..to + row].copy_from_slice(&other.channel[from..from + row]);
+        }
+    }
+
+    /// Mean escape count of the samples at a pixel. Points inside the set
+    /// count as the full iteration depth.
+    pub fn mean_iterations(&self, x: u32, y: u32) -> f32 {
+        let counts = &self.iterations[self.sample_range(x, y)];
+        counts.iter().map(|&count| count as f32).sum::<f32>() / counts.len() as f32
+    }
+}
+
+/// Escape data of an earlier single sample render, e.g. at a lower
+/// resolution, along with the view it was computed for. Samples of a new
+/// render that land on exactly the same point, down to the last bit, are
+/// taken from it instead of being iterated again, so the result matches a
+/// render from scratch. That happens for every other pixel of a power of two
+/// upscale with a sample offset of 0, and for the center pixels of an odd
+/// upscale with centered samples wherever rounding happens to agree.
+#[derive(Clone, Debug)]
+pub struct PriorGrid {
+    view: RenderParams,
+    grid: EscapeGrid
+}
+
+impl PriorGrid {
+    /// Fails unless `view`, the parameters `grid` was computed with, iterates
+    /// exactly like `params` and covers the full depth of a linear view.
+    pub fn new(view: RenderParams, grid: EscapeGrid, params: &RenderParams) -> Result<PriorGrid, MandelbrotError> {
+        let same_iteration = view.fractal == params.fractal && view.iterations == params.iterations
+            && view.newton_iterations == params.newton_iterations
+            && view.bailout == params.bailout && view.bailout_shape == params.bailout_shape && view.power == params.power
+            && view.convergence_eps == params.convergence_eps && view.julia == params.julia;
+
+        if !same_iteration {
+            return Err(MandelbrotError::InvalidParameter("the prior grid was computed with different iteration settings".to_string()));
+        }
+
+        if view.mapping != Mapping::Linear || view.iteration_mask.is_some() {
+            return Err(MandelbrotError::InvalidParameter(
+                "only grids of full depth renders with the linear mapping can be refined".to_string()
+            ));
+        }
+
+        if grid.samples != 1 || grid.width != view.width || grid.height != view.height {
+            return Err(MandelbrotError::InvalidParameter("only grids of single sample renders can be refined".to_string()));
+        }
+
+        if params.mapping != Mapping::Linear || params.iteration_mask.is_some() || matches!(params.index_channel, IndexChannel::Distance | IndexChannel::OrbitTrap) {
+            return Err(MandelbrotError::InvalidParameter(
+                "refining needs the linear mapping, no iteration mask and the iteration or potential index channel".to_string()
+            ));
+        }
+
+        let prior = PriorGrid { view, grid };
+
+        if !prior.shares_samples(params) {
+            return Err(MandelbrotError::InvalidParameter(
+                "no sample of the new render lands on one of the prior grid, use --sample-offset 0 for power of two upscales or an odd factor with centered samples".to_string()
+            ));
+        }
+
+        Ok(prior)
+    }
+
+    /// Whether at least one sample of a render with `params` can be taken
+    /// from this grid.
+    fn shares_samples(&self, params: &RenderParams) -> bool {
+        let offsets = sample_offsets(params.sample_pattern, params.samples, params.sample_offset);
+
+        (0..params.height).any(|y| (0..params.width).any(|x| offsets.iter().any(|&(dx, dy)| {
+            self.escape_at(params.point(x as f32 + dx, y as f32 + dy)).is_some()
+        })))
+    }
+
+    /// The earlier result for the point `c`, if one of its samples was at
+    /// exactly that point.
+    pub fn escape_at(&self, c: Complex<f32>) -> Option<Escape> {
+        let offset = self.view.sample_offset;
+        let (px, py) = self.view.pixel(c);
+        let (x, y) = ((px - offset).round(), (py - offset).round());
+
+        if !(x >= 0.0 && y >= 0.0 && x < self.view.width as f32 && y < self.view.height as f32) {
+            return None;
+        }
+
+        if self.view.point(x + offset, y + offset) != c {
+            return None;
+        }
+
+        Some(self.grid.get(y as usize * self.grid.width as usize + x as usize))
+    }
+}
+
+/// Pixel offsets under which a Julia set view is point symmetric about the
+/// origin, if it is. The orbit of `-z` matches that of `z` after the first
+/// squaring, so the pixel at `(x, y)` then mirrors the one at
+/// `(sx - x, sy - y)`. This only holds for single sample renders centered on
+/// the origin whose sample offset lands mirrored samples on whole pixels,
+/// and only for even powers: `(-z)^p = z^p` does not hold for odd ones.
+/// Depending on the dimensions, rounding in the pixel to coordinate mapping
+/// can leave mirrored samples slightly off from each other, so every row and
+/// column is checked to map onto exactly the negated coordinate.
+fn julia_symmetry(params: &RenderParams, offsets: &[(f32, f32)]) -> Option<(i64, i64)> {
+    if !params.symmetry || params.fractal != Fractal::Julia || !params.power.is_multiple_of(2) || params.center != (0.0, 0.0)
+        || offsets.len() != 1 || params.iteration_mask.is_some() || params.mapping != Mapping::Linear {
+        return None;
+    }
+
+    let (ox, oy) = (offsets[0].0 * 2.0, offsets[0].1 * 2.0);
+
+    if ox.fract() != 0.0 || oy.fract() != 0.0 {
+        return None;
+    }
+
+    let (sx, sy) = (params.width as i64 - ox as i64, params.height as i64 - oy as i64);
+    let (dx, dy) = offsets[0];
+
+    let columns_mirror = (0..params.width as i64).filter(|&x| (0..params.width as i64).contains(&(sx - x))).all(|x| {
+        params.point(x as f32 + dx, dy).re == -params.point((sx - x) as f32 + dx, dy).re
+    });
+    let rows_mirror = (0..params.height as i64).filter(|&y| (0..params.height as i64).contains(&(sy - y))).all(|y| {
+        params.point(dx, y as f32 + dy).im == -params.point(dx, (sy - y) as f32 + dy).im
+    });
+
+    if !columns_mirror || !rows_mirror {
+        return None;
+    }
+
+    Some((sx, sy))
+}
+
+/// How tiles are handed out to the worker threads.
+///
+/// `Static` splits the tiles between the threads up front, which avoids any
+/// coordination and works best when all tiles take about as long. `Dynamic`
+/// lets idle threads pick up the next pending tile, which keeps every thread
+/// busy when some tiles (e.g. ones entirely inside the set at a low depth, or
+/// entirely outside it) finish much sooner than others.
+#[derive(clap::ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Schedule {
+    Static,
+    Dynamic
+}
+
+/// Order in which tiles are queued. Within a tile pixels are always visited
+/// row by row, matching the layout of the output buffer, so this mostly
+/// changes how the image fills in while rendering: `Morton` follows a
+/// Z-order curve and grows detail in patches rather than in horizontal
+/// stripes. The result is the same either way.
+#[derive(clap::ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum TileOrder {
+    RowMajor,
+    Morton
+}
+
+/// Interleaves the bits of the two coordinates into a Z-order index.
+fn morton_index(x: u32, y: u32) -> u64 {
+    let spread = |value: u32| {
+        let mut value = value as u64;
+        value = (value | (value << 16)) & 0x0000_ffff_0000_ffff;
+        value = (value | (value << 8)) & 0x00ff_00ff_00ff_00ff;
+        value = (value | (value << 4)) & 0x0f0f_0f0f_0f0f_0f0f;
+        value = (value | (value << 2)) & 0x3333_3333_3333_3333;
+        (value | (value << 1)) & 0x5555_5555_5555_5555
+    };
+
+    spread(x) | (spread(y) << 1)
+}
+
+/// A rectangle of pixels computed as one unit of work.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Tile {
+    pub x: u32,
+    pub y: u32,
+    pub width: u32,
+    pub height: u32
+}
+
+/// Splits a `width` by `height` area into square tiles of the given size,
+/// with smaller tiles along the right and bottom edges.
+pub fn tiles(width: u32, height: u32, size: u32) -> Vec<Tile> {
+    let size = size.max(1);
+    let mut tiles = Vec::new();
+
+    for y in (0..height).step_by(size as usize) {
+        for x in (0..width).step_by(size as usize) {
+            tiles.push(Tile { x, y, width: size.min(width - x), height: size.min(height - y) });
+        }
+    }
+
+    tiles
+}
+
+/// Probes the corners and the center of a tile for whether most of them are
+/// in the set. Escaped points never take the fast paths, so they only pay
+/// off in tiles dominated by the interior, while boundary tiles are cheaper
+/// to iterate straight.
+fn probe_interior(tile: &Tile, params: &RenderParams) -> bool {
+    let (right, bottom) = (tile.x + tile.width - 1, tile.y + tile.height - 1);
+    let probes = [(tile.x, tile.y), (right, tile.y), (tile.x, bottom), (right, bottom), (tile.x + tile.width / 2, tile.y + tile.height / 2)];
+
+    let inside = probes.iter().filter(|&&(x, y)| {
+        let c = params.point(x as f32 + params.sample_offset, y as f32 + params.sample_offset);
+        escape_fast(c, params, params.iteration_cap(x, y)).in_set
+    }).count();
+
+    inside * 2 > probes.len()
+}
+
+/// Escape data of a tile, as a grid the size of the tile.
+fn compute_tile(tile: &Tile, params: &RenderParams, offsets: &[(f32, f32)]) -> EscapeGrid {
+    let count = tile.width as usize * tile.height as usize * offsets.len();
+    let fast = params.fast_paths_apply() && probe_interior(tile, params);
+
+    if fast {
+        params.count(|stats| &stats.fast_path_tiles, 1);
+    }
+
+    let mut grid = EscapeGrid {
+        width: tile.width,
+        height: tile.height,
+        samples: offsets.len() as u32,
+        in_set: Vec::with_capacity(count),
+        iterations: Vec::with_capacity(count),
+        z: Vec::with_capacity(count),
+        channel: Vec::with_capacity(count)
+    };
+
+    for y in tile.y..tile.y + tile.height {
+        for x in tile.x..tile.x + tile.width {
+            let cap = params.iteration_cap(x, y);
+
+            for &(dx, dy) in offsets {
+                let c = params.point(x as f32 + dx, y as f32 + dy);
+
+                if let Some(escape) = params.prior.as_ref().and_then(|prior| prior.escape_at(c)) {
+                    params.count(|stats| &stats.reused_samples, 1);
+                    grid.push(escape);
+                    continue;
+                }
+
+                grid.push(if fast { escape_fast(c, params, cap) } else { escape_with_cap(c, params, cap) });
+            }
+        }
+    }
+
+    grid
+}
+
+/// Computes the given tiles on the worker threads, in the configured order
+/// and schedule, and copies each into `grid` with the pixel at `origin` as
+/// its top-left corner. Returns whether all of them were computed, which is
+/// only not the case once the render was cancelled.
+fn compute_tiles<F: FnMut(u64)>(
+    params: &RenderParams, offsets: &Arc<Vec<(f32, f32)>>, mut pending: Vec<Tile>,
+    grid: &mut EscapeGrid, origin: (u32, u32), on_progress: &mut F
+) -> Result<bool, MandelbrotError> {
+    let pool = ThreadPool::new(params.threads);
+    let (tx, rx) = sync_channel(params.queue_depth());
+
+    if params.tile_order == TileOrder::Morton {
+        pending.sort_by_key(|tile| morton_index(tile.x / params.tile_size, tile.y / params.tile_size));
+    }
+
+    let tile_count = pending.len();
+    let pending = Arc::new(pending);
+    let next = Arc::new(AtomicUsize::new(0));
+    let sent = Arc::new(AtomicU64::new(0));
+
+    // One job per thread, each pulling tiles until none are left. Workers
+    // block on the bounded channel while the merge falls behind, so at most
+    // the queue depth plus one tile per thread is ever held at once.
+    for thread in 0..params.threads {
+        let tx = tx.clone();
+        let params = params.clone();
+        let offsets = offsets.clone();
+        let pending = pending.clone();
+        let next = next.clone();
+        let sent = sent.clone();
+
+        pool.execute(move|| {
+            let mut own = (thread..pending.len()).step_by(params.threads);
+
+            while !params.is_cancelled() {
+                let index = match params.schedule {
+                    Schedule::Dynamic => Some(next.fetch_add(1, Ordering::Relaxed)),
+                    Schedule::Static => own.next()
+                };
+
+                let tile = match index.and_then(|index| pending.get(index)) {
+                    Some(&tile) => tile,
+                    None => break
+                };
+
+                // The receiver only goes away if rendering was aborted.
+                if tx.send((tile, compute_tile(&tile, &params, &offsets))).is_err() {
+                    break;
+                }
+
+                sent.fetch_add(1, Ordering::Relaxed);
+            }
+        });
+    }
+
+    drop(tx);
+
+    let mut count = 0;
+
+    rx.iter().take(tile_count).for_each(|(tile, tile_grid)| {
+        if let Some(stats) = &params.stats {
+            // Tiles still waiting behind this one. Sends are counted once
+            // they went through, so this never overestimates.
+            let queued = sent.load(Ordering::Relaxed).saturating_sub(count as u64 + 1);
+            stats.peak_queued_tiles.fetch_max(queued, Ordering::Relaxed);
+        }
+
+        grid.copy_from(&tile_grid, tile.x - origin.0, tile.y - origin.1);
+
+        count += 1;
+        on_progress(tile.width as u64 * tile.height as u64);
+    });
+
+    if count < tile_count {
+        if params.is_cancelled() {
+            return Ok(false);
+        }
+
+        return Err(MandelbrotError::Render(
+            format!("only {} of {} tiles were computed", count, tile_count)
+        ));
+    }
+
+    Ok(true)
+}
+
+/// First pass of a render: iterates every sample point and collects the
+/// results into a grid, one tile per work unit. Centered Julia set views only
+/// compute the top half of the rows and reflect the rest through the origin.
+pub fn compute_grid<F: FnMut(u64)>(params: &RenderParams, on_progress: F) -> Result<EscapeGrid, MandelbrotError> {
+    match compute_partial_grid(params, on_progress)? {
+        (grid, true) => Ok(grid),
+        (_, false) => Err(MandelbrotError::Cancelled("not all tiles were computed".to_string()))
+    }
+}
+
+/// Like `compute_grid`, but a cancelled render still returns the tiles
+/// computed so far, along with whether the grid is complete. Missing pixels
+/// are filled in as if they were inside the set.
+pub fn compute_partial_grid<F: FnMut(u64)>(params: &RenderParams, mut on_progress: F) -> Result<(EscapeGrid, bool), MandelbrotError> {
+    params.validate()?;
+
+    let (width, height) = (params.width, params.height);
+    let offsets = Arc::new(sample_offsets(params.sample_pattern, params.samples, params.sample_offset));
+
+    let symmetry = julia_symmetry(params, &offsets);
+    let mirrored_row = |y: u32| symmetry.map(|(_, sy)| sy - y as i64).filter(|&my| my >= 0 && my < y as i64);
+    let computed_height = (0..height).take_while(|&y| mirrored_row(y).is_none()).count() as u32;
+
+    let placeholder = Escape { in_set: true, iterations: params.iterations, z: Complex::new(0.0, 0.0), channel: 0.0 };
+    let mut grid = EscapeGrid::filled(width, height, offsets.len() as u32, placeholder);
+
+    let pending = tiles(width, computed_height, params.tile_size);
+
+    if !compute_tiles(params, &offsets, pending, &mut grid, (0, 0), &mut on_progress)? {
+        return Ok((grid, false));
+    }
+
+    if let Some((sx, _)) = symmetry {
+        let (dx, dy) = offsets[0];
+
+        for y in 0..height {
+            let my = match mirrored_row(y) {
+                Some(my) => my as usize,
+                None => continue
+            };
+
+            for x in 0..width {
+                let mx = sx - x as i64;
+
+                let escape = if mx >= 0 && mx < width as i64 {
+                    let mut mirrored = grid.get(my * width as usize + mx as usize);
+
+                    // Points that never got squared still sit at their start.
+                    if mirrored.iterations == 0 {
+                        mirrored.z = -mirrored.z;
+                    }
+
+                    mirrored
+                } else {
+                    escape_with_cap(params.point(x as f32 + dx, y as f32 + dy), params, params.iterations)
+                };
+
+                grid.set(y as usize * width as usize + x as usize, escape);
+            }
+
+            on_progress(width as u64);
+        }
+    }
+
+    Ok((grid, true))
+}
+
+/// Renders just the given rectangle of the full view described by `params`,
+/// split into tiles the same way as a full render. Pixels come out exactly as
+/// they would in a full render; only the symmetry shortcut is not taken.
+pub fn render_region(params: &RenderParams, region: &Tile) -> Result<image::RgbImage, MandelbrotError> {
+    params.validate()?;
+
+    if region.width == 0 || region.height == 0
+        || region.x as u64 + region.width as u64 > params.width as u64
+        || region.y as u64 + region.height as u64 > params.height as u64 {
+        return Err(MandelbrotError::InvalidParameter(format!(
+            "region {}x{} at ({}, {}) does not fit into the {}x{} image",
+            region.width, region.height, region.x, region.y, params.width, params.height
+        )));
+    }
+
+    let offsets = Arc::new(sample_offsets(params.sample_pattern, params.samples, params.sample_offset));
+
+    let pending: Vec<Tile> = tiles(region.width, region.height, params.tile_size).into_iter().map(|tile| {
+        Tile { x: tile.x + region.x, y: tile.y + region.y, ..tile }
+    }).collect();
+
+    let placeholder = Escape { in_set: true, iterations: params.iterations, z: Complex::new(0.0, 0.0), channel: 0.0 };
+    let mut grid = EscapeGrid::filled(region.width, region.height, offsets.len() as u32, placeholder);
+
+    if !compute_tiles(params, &offsets, pending, &mut grid, (region.x, region.y), &mut |_| {})? {
+        return Err(MandelbrotError::Cancelled("not all tiles of the region were computed".to_string()));
+    }
+
+    Ok(colorize(&grid, params))
+}
+
+/// Whether two rows of the grid end up with the same colors because their
+/// escape data matches in everything the coloring looks at.
+fn rows_match(grid: &EscapeGrid, params: &RenderParams, a: u32, b: u32) -> bool {
+    let row = grid.width as usize * grid.samples as usize;
+    let ignore_interior = params.interior == InteriorMode::Solid || params.fractal == Fractal::Newton;
+
+    (0..row).all(|offset| {
+        let (i, j) = (a as usize * row + offset, b as usize * row + offset);
+
+        grid.in_set[i] == grid.in_set[j] && ((grid.in_set[i] && ignore_interior)
+            || (grid.iterations[i] == grid.iterations[j] && grid.z[i] == grid.z[j] && grid.channel[i] == grid.channel[j]))
+    })
+}
+
+/// Second pass of a render: turns the escape data into colors, averaging the
+/// samples of each pixel. Rows whose escape data matches the row above, as
+/// is common deep inside the set, reuse its colors instead.
+pub fn colorize(grid: &EscapeGrid, params: &RenderParams) -> image::RgbImage {
+    let mut imgbuf = image::ImageBuffer::new(grid.width, grid.height);
+    let row_bytes = grid.width as usize * 3;
+
+    for y in 0..grid.height {
+        if y > 0 && rows_match(grid, params, y - 1, y) {
+            let start = y as usize * row_bytes;
+            imgbuf.copy_within(start - row_bytes..start, start);
+
+            params.count(|stats| &stats.repeated_rows, 1);
+            continue;
+        }
+
+        for x in 0..grid.width {
+            imgbuf.put_pixel(x, y, sample_color(grid.samples_at(x, y), params));
+        }
+    }
+
+    imgbuf
+}
+
+/// Escape counts divided by the iteration depth as 32-bit floats, averaged
+/// over the samples of each pixel, for output formats that keep the full
+/// range. Unlike `colorize` nothing is clamped or quantized, and points inside
+/// the set are 1.
+pub fn normalized_escapes(grid: &EscapeGrid, params: &RenderParams) -> image::Rgb32FImage {
+    let depth = params.iterations.max(1) as f32;
+
+    image::ImageBuffer::from_fn(grid.width, grid.height, |x, y| {
+        let samples = grid.samples_at(x, y);
+        let count = samples.len();
+
+        let total: f32 = samples.map(|escape| {
+            if escape.in_set {
+                1.0
+            } else if params.smooth {
+                smooth_iterations(escape.iterations, escape.z, params.bailout, params.power) / depth
+            } else {
+                escape.iterations as f32 / depth
+            }
+        }).sum();
+
+        let value = total / count as f32;
+        image::Rgb([value, value, value])
+    })
+}
+
+/// Region of the image kept by `isolate_region`.
+#[derive(clap::ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Region {
+    Interior,
+    Exterior
+}
+
+/// Makes every pixel outside the given region transparent. A pixel belongs
+/// to the interior if most of its samples stayed bounded. With `soft_edge`
+/// the alpha is instead the fraction of samples inside the region, giving
+/// supersampled renders an anti-aliased cutout for compositing.
+pub fn isolate_region(imgbuf: &image::RgbImage, grid: &EscapeGrid, region: Region, soft_edge: bool) -> image::RgbaImage {
+    image::ImageBuffer::from_fn(grid.width, grid.height, |x, y| {
+        let samples = &grid.in_set[grid.sample_range(x, y)];
+        let in_set = samples.iter().filter(|&&in_set| in_set).count();
+        let [r, g, b] = imgbuf.get_pixel(x, y).0;
+
+        let alpha = if soft_edge {
+            let inside = if region == Region::Interior { in_set } else { samples.len() - in_set };
+            ((inside * 255 + samples.len() / 2) / samples.len()) as u8
+        } else if (in_set * 2 > samples.len()) == (region == Region::Interior) {
+            255
+        } else {
+            0
+        };
+
+        image::Rgba([r, g, b, alpha])
+    })
+}
+
+pub fn render<F: FnMut(u64)>(params: &RenderParams, on_progress: F) -> Result<image::RgbImage, MandelbrotError> {
+    let grid = compute_grid(params, on_progress)?;
+    Ok(colorize(&grid, params))
+}
+
+/// Renders straight into a caller provided RGBA buffer, which must hold
+/// exactly `width * height * 4` bytes. This allows a host application to
+/// reuse one framebuffer across frames.
+pub fn render_into(buffer: &mut [u8], params: &RenderParams) -> Result<(), MandelbrotError> {
+    let expected = params.width as usize * params.height as usize * 4;
+
+    if buffer.len() != expected {
+        return Err(MandelbrotError::InvalidParameter(
+            format!("buffer holds {} bytes but {}x{} RGBA needs {}", buffer.len(), params.width, params.height, expected)
+        ));
+    }
+
+    let grid = compute_grid(params, |_| {})?;
+
+    for (index, pixel) in buffer.chunks_exact_mut(4).enumerate() {
+        let (x, y) = ((index % grid.width as usize) as u32, (index / grid.width as usize) as u32);
+        let [r, g, b] = sample_color(grid.samples_at(x, y), params).0;
+
+        pixel.copy_from_slice(&[r, g, b, 255]);
+    }
+
+    Ok(())
+}
+
+/// Color of the single point `re + im·i`, iterated and colored exactly like
+/// a sample of a full render, without spawning threads or allocating. This
+/// matches the pixel of `render` whose only sample lands on that point,
+/// unless an iteration mask gives that pixel its own depth.
+pub fn pixel_color(re: f32, im: f32, params: &RenderParams) -> image::Rgb<u8> {
+    sample_color(std::iter::once(escape(Complex::new(re, im), params)), params)
+}
+
+fn sample_color(mut samples: impl ExactSizeIterator<Item = Escape>, params: &RenderParams) -> image::Rgb<u8> {
+    let count = samples.len() as u32;
+
+    if count == 1 {
+        return samples.next().map_or(image::Rgb([0, 0, 0]), |escape| escape_color(&escape, params));
+    }
+
+    let mut sum = [0u32; 3];
+
+    for escape in samples {
+        let color = escape_color(&escape, params);
+
+        for (total, channel) in sum.iter_mut().zip(color.0) {
+            *total += channel as u32;
+        }
+    }
+
+    image::Rgb(sum.map(|total| ((total + count / 2) / count) as u8))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::builder::RenderBuilder;
+
+    #[test]
+    fn radii_set_the_extent_of_each_axis() {
+        let params = RenderBuilder::new().dimensions(200, 100).center(-0.5, 0.25).radii(3.0, 0.5).build().unwrap();
+
+        assert_eq!(params.point(0.0, 0.0), Complex::new(-2.0, 0.5));
+        assert_eq!(params.point(200.0, 100.0), Complex::new(1.0, 0.0));
+        assert_eq!(params.point(100.0, 50.0), Complex::new(-0.5, 0.25));
+    }
+
+    #[test]
+    fn render_into_fills_the_buffer_like_render() {
+        let params = RenderBuilder::new().dimensions(40, 30).iterations(64).samples(2).color(true).build().unwrap();
+        let mut buffer = vec![0; 40 * 30 * 4];
+
+        render_into(&mut buffer, &params).unwrap();
+
+        let expected: Vec<u8> = render(&params, |_| {}).unwrap().pixels().flat_map(|pixel| [pixel[0], pixel[1], pixel[2], 255]).collect();
+        assert_eq!(buffer, expected);
+
+        let error = render_into(&mut buffer[4..], &params).unwrap_err();
+        assert!(matches!(error, MandelbrotError::InvalidParameter(_)), "{}", error);
+    }
+
+    #[test]
+    fn mirrored_julia_matches_a_full_render() {
+        for (width, height, offset, mirrored) in [(64, 48, 0.5, true), (32, 32, 0.0, true), (40, 30, 0.5, false), (41, 31, 0.5, false)] {
+            let builder = || RenderBuilder::new().dimensions(width, height).fractal(Fractal::Julia).julia(-0.8, 0.156)
+                .center(0.0, 0.0).radius(3.0).iterations(100).sample_offset(offset).color(true);
+
+            let params = builder().symmetry(true).build().unwrap();
+            assert_eq!(julia_symmetry(&params, &[(offset, offset)]).is_some(), mirrored, "{}x{} at offset {}", width, height, offset);
+
+            let full = render(&builder().symmetry(false).build().unwrap(), |_| {}).unwrap();
+            assert_eq!(render(&params, |_| {}).unwrap(), full, "{}x{} at offset {}", width, height, offset);
+        }
+    }
+
+    #[test]
+    #[ignore = "timing benchmark, run in release mode with --ignored"]
+    fn dynamic_schedule_is_not_slower_than_static() {
+        let time = |schedule: Schedule| {
+            let params = RenderBuilder::new().dimensions(600, 600).iterations(2000).threads(4).schedule(schedule)
+                .fast_paths(false).build().unwrap();
+
+            (0..3).map(|_| {
+                let start = std::time::Instant::now();
+                compute_grid(&params, |_| {}).unwrap();
+                start.elapsed()
+            }).min().unwrap()
+        };
+
+        // The default view has its interior, which costs the full depth, all in the middle rows.
+        let (fixed, dynamic) = (time(Schedule::Static), time(Schedule::Dynamic));
+        assert!(dynamic.as_secs_f64() <= fixed.as_secs_f64() * 1.1, "dynamic {:?}, static {:?}", dynamic, fixed);
+    }
+
+    #[test]
+    fn first_pixel_is_sampled_at_its_offset() {
+        // Pixels 0.02 wide, the corner pixel spanning -2 to -1.98 and 1 to 0.98.
+        for (offset, expected) in [(0.0, Complex::new(-2.0, 1.0)), (0.5, Complex::new(-1.99, 0.99))] {
+            let params = RenderBuilder::new().dimensions(200, 100).center(0.0, 0.0).radii(4.0, 2.0).iterations(64)
+                .sample_offset(offset).build().unwrap();
+            let offsets = sample_offsets(params.sample_pattern, params.samples, params.sample_offset);
+            let sample = params.point(offsets[0].0, offsets[0].1);
+
+            assert!((sample - expected).norm() < 1e-6, "offset {} samples {}", offset, sample);
+            assert_eq!(compute_grid(&params, |_| {}).unwrap().get(0), escape(sample, &params));
+        }
+    }
+
+    #[test]
+    fn interior_cutout_keeps_exactly_the_points_in_the_set() {
+        let params = RenderBuilder::new().dimensions(60, 40).iterations(64).color(true).fast_paths(false).build().unwrap();
+        let grid = compute_grid(&params, |_| {}).unwrap();
+        let imgbuf = colorize(&grid, &params);
+
+        let interior = isolate_region(&imgbuf, &grid, Region::Interior, false);
+        let exterior = isolate_region(&imgbuf, &grid, Region::Exterior, false);
+
+        for (x, y, pixel) in interior.enumerate_pixels() {
+            let c = params.point(x as f32 + 0.5, y as f32 + 0.5);
+            let in_set = crate::mandelbrot::in_mandelbrot_set(c, params.iterations, params.bailout, params.bailout_shape, params.power).0;
+
+            assert_eq!(pixel[3] == 255, in_set, "pixel {}, {}", x, y);
+            assert_eq!(exterior.get_pixel(x, y)[3] == 255, !in_set, "pixel {}, {}", x, y);
+            assert_eq!(pixel.0[..3], imgbuf.get_pixel(x, y).0);
+        }
+    }
+
+    #[test]
+    fn normalized_escapes_round_trip_through_exr() {
+        let params = RenderBuilder::new().dimensions(40, 30).iterations(64).smooth(true).build().unwrap();
+        let escapes = normalized_escapes(&compute_grid(&params, |_| {}).unwrap(), &params);
+        let path = std::env::temp_dir().join(format!("mandelbrot-escapes-{}.exr", std::process::id()));
+
+        escapes.save(&path).unwrap();
+        let loaded = image::open(&path).unwrap().into_rgb32f();
+        std::fs::remove_file(&path).unwrap();
+
+        assert_eq!(loaded.dimensions(), escapes.dimensions());
+        // Smooth counts fall between the 8-bit levels, which EXR has to keep.
+        assert!(escapes.pixels().any(|pixel| (pixel[0] * 255.0).fract().abs() > 0.01));
+
+        for (loaded, expected) in loaded.pixels().zip(escapes.pixels()) {
+            for channel in 0..3 {
+                assert!((loaded[channel] - expected[channel]).abs() < 1e-6, "{} instead of {}", loaded[channel], expected[channel]);
+            }
+        }
+    }
+
+    #[test]
+    fn soft_edges_give_the_fraction_of_samples_in_the_region() {
+        let params = RenderBuilder::new().dimensions(60, 40).iterations(64).samples(4).build().unwrap();
+        let grid = compute_grid(&params, |_| {}).unwrap();
+        let imgbuf = colorize(&grid, &params);
+
+        let interior = isolate_region(&imgbuf, &grid, Region::Interior, true);
+        let exterior = isolate_region(&imgbuf, &grid, Region::Exterior, true);
+        let mut partial = 0;
+
+        for (x, y, pixel) in interior.enumerate_pixels() {
+            let inside = grid.in_set[grid.sample_range(x, y)].iter().filter(|&&in_set| in_set).count();
+
+            assert_eq!(pixel[3] as usize, (inside * 255 + 2) / 4, "pixel {}, {}", x, y);
+            assert_eq!(exterior.get_pixel(x, y)[3] as usize, ((4 - inside) * 255 + 2) / 4, "pixel {}, {}", x, y);
+
+            if pixel[3] > 0 && pixel[3] < 255 {
+                partial += 1;
+            }
+        }
+
+        assert!(partial > 0);
+    }
+
+    #[test]
+    fn morton_order_renders_like_row_major() {
+        assert_eq!((0..4).map(|i| morton_index(i % 2, i / 2)).collect::<Vec<_>>(), [0, 1, 2, 3]);
+        assert_eq!(morton_index(2, 0), 4);
+        assert_eq!(morton_index(3, 3), 15);
+
+        for schedule in [Schedule::Static, Schedule::Dynamic] {
+            let builder = || RenderBuilder::new().dimensions(90, 70).iterations(64).tile_size(16).threads(3).schedule(schedule).color(true);
+
+            let row_major = render(&builder().tile_order(TileOrder::RowMajor).build().unwrap(), |_| {}).unwrap();
+            let morton = render(&builder().tile_order(TileOrder::Morton).build().unwrap(), |_| {}).unwrap();
+
+            assert_eq!(morton, row_major, "{:?} schedule", schedule);
+        }
+    }
+
+    #[test]
+    fn escaped_pixels_colored_black_are_still_written() {
+        let black = Some(Arc::new(vec![image::Rgb([0, 0, 0]); 2]));
+        let params = RenderBuilder::new().dimensions(40, 30).iterations(64).color(true).gradient(black).build().unwrap();
+        let grid = compute_grid(&params, |_| {}).unwrap();
+        let mut buffer = vec![0xab; 40 * 30 * 4];
+
+        render_into(&mut buffer, &params).unwrap();
+
+        assert!(grid.in_set.iter().any(|&in_set| !in_set));
+        assert!(buffer.chunks_exact(4).all(|pixel| pixel == [0, 0, 0, 255]));
+    }
+
+    #[test]
+    fn log_polar_pixels_map_onto_rings_around_the_center() {
+        let params = RenderBuilder::new().dimensions(360, 100).center(-0.75, 0.0)
+            .mapping(Mapping::LogPolar { log_radius: (-5.0, 1.0) }).build().unwrap();
+        let e = std::f32::consts::E;
+
+        // Columns sweep the angle, rows the log of the distance from the top down.
+        for ((x, y), expected) in [
+            ((0.0, 0.0), Complex::new(-0.75 + e, 0.0)),
+            ((90.0, 100.0), Complex::new(-0.75, e.powi(-5))),
+            ((180.0, 50.0), Complex::new(-0.75 - e.powi(-2), 0.0)),
+            ((270.0, 100.0 / 6.0), Complex::new(-0.75, -1.0))
+        ] {
+            let point = params.point(x, y);
+            assert!((point - expected).norm() < 1e-5, "pixel ({}, {}) maps to {} instead of {}", x, y, point, expected);
+        }
+    }
+
+    #[test]
+    fn grid_holds_the_escape_of_every_sample() {
+        let params = RenderBuilder::new().dimensions(40, 30).iterations(64).samples(4).color(true).fast_paths(false).build().unwrap();
+        let grid = compute_grid(&params, |_| {}).unwrap();
+        let offsets = sample_offsets(params.sample_pattern, params.samples, params.sample_offset);
+
+        // One `Escape` per sample, as computed and stored point by point.
+        let mut escapes = Vec::new();
+
+        for y in 0..30 {
+            for x in 0..40 {
+                for &(dx, dy) in &offsets {
+                    escapes.push(escape(params.point(x as f32 + dx, y as f32 + dy), &params));
+                }
+            }
+        }
+
+        assert_eq!(grid.escapes().collect::<Vec<_>>(), escapes);
+
+        let imgbuf = colorize(&grid, &params);
+
+        for (x, y, pixel) in imgbuf.enumerate_pixels() {
+            let index = (y * 40 + x) as usize * 4;
+            assert_eq!(*pixel, sample_color(escapes[index..index + 4].iter().copied(), &params));
+        }
+    }
+
+    #[test]
+    fn cancelled_render_keeps_the_finished_tiles_at_full_size() {
+        let cancel = Arc::new(AtomicBool::new(false));
+        let params = RenderParams {
+            cancel: Some(cancel.clone()),
+            ..RenderBuilder::new().dimensions(128, 96).iterations(64).tile_size(16).threads(1).color(true).build().unwrap()
+        };
+
+        // Cancel as if Ctrl-C was pressed after the first tile.
+        let (grid, complete) = compute_partial_grid(&params, |_| cancel.store(true, Ordering::Relaxed)).unwrap();
+        assert!(!complete);
+
+        let partial = colorize(&grid, &params);
+        let full = render(&RenderParams { cancel: None, ..params.clone() }, |_| {}).unwrap();
+
+        assert_eq!(partial.dimensions(), (128, 96));
+        assert_eq!(grid.len(), 128 * 96);
+        // The first tile was kept, the ones never computed are left as interior.
+        assert!((0..16).all(|y| (0..16).all(|x| partial.get_pixel(x, y) == full.get_pixel(x, y))));
+        assert!((112..128).all(|x| *partial.get_pixel(x, 95) == image::Rgb([0, 0, 0])));
+        assert_ne!(partial, full);
+    }
+
+    #[test]
+    fn only_interior_tiles_take_the_fast_paths() {
+        // One tile entirely in the set, one of the whole set with its corners
+        // escaping, one entirely outside of it and one of the period two bulb
+        // with a bailout small enough for all of it to escape.
+        let views = [((-0.1, 0.0, 0.05, 2.0), 1), ((-0.75, 0.0, 3.0, 2.0), 0), ((2.0, 2.0, 0.5, 2.0), 0), ((-1.2, 0.0, 0.1, 1.1), 0)];
+
+        for ((re, im, radius, bailout), fast_tiles) in views {
+            let stats = Arc::new(RenderStats::default());
+            let builder = || RenderBuilder::new().dimensions(32, 32).tile_size(32).center(re, im).radius(radius).iterations(64)
+                .bailout(bailout).color(true);
+            let params = RenderParams { stats: Some(stats.clone()), ..builder().build().unwrap() };
+
+            let imgbuf = render(&params, |_| {}).unwrap();
+
+            assert_eq!(stats.fast_path_tiles.load(Ordering::Relaxed), fast_tiles, "view at {} + {}i", re, im);
+            assert_eq!(imgbuf, render(&builder().fast_paths(false).build().unwrap(), |_| {}).unwrap());
+        }
+    }
+
+    #[test]
+    fn queued_tiles_never_exceed_the_queue_depth() {
+        for (threads, depth) in [(1, 2), (4, 2), (3, 8)] {
+            let stats = Arc::new(RenderStats::default());
+            let params = RenderParams {
+                stats: Some(stats.clone()),
+                ..RenderBuilder::new().dimensions(128, 96).tile_size(8).threads(threads).queue_depth(Some(depth))
+                    .iterations(64).build().unwrap()
+            };
+
+            // A slow merge lets the workers fill up the queue. A single slot would
+            // already be empty again by the time the merge looks at it.
+            compute_grid(&params, |_| std::thread::sleep(std::time::Duration::from_micros(200))).unwrap();
+
+            let peak = stats.peak_queued_tiles.load(Ordering::Relaxed);
+            assert!(peak >= 1 && peak <= depth as u64, "{} tiles queued with a depth of {}", peak, depth);
+        }
+    }
+
+    #[test]
+    fn pixel_color_matches_the_rendered_pixel() {
+        let params = RenderBuilder::new().dimensions(60, 40).center(-0.75, 0.0).radius(3.0).iterations(100)
+            .color(true).smooth(true).build().unwrap();
+        let imgbuf = render(&params, |_| {}).unwrap();
+
+        for (x, y) in [(0, 0), (30, 20), (12, 33), (59, 39), (45, 7)] {
+            let c = params.point(x as f32 + params.sample_offset, y as f32 + params.sample_offset);
+            assert_eq!(pixel_color(c.re, c.im, &params), *imgbuf.get_pixel(x, y), "pixel {}, {}", x, y);
+        }
+    }
+
+    #[test]
+    fn repeated_rows_are_copied_without_changing_the_pixels() {
+        for ((re, im, radius), all_repeated) in [((-0.1, 0.0, 0.05), true), ((-0.75, 0.0, 3.0), false)] {
+            let stats = Arc::new(RenderStats::default());
+            let params = RenderParams {
+                stats: Some(stats.clone()),
+                ..RenderBuilder::new().dimensions(32, 24).center(re, im).radius(radius).iterations(64).color(true).build().unwrap()
+            };
+
+            let grid = compute_grid(&params, |_| {}).unwrap();
+            let imgbuf = colorize(&grid, &params);
+            let repeated = stats.repeated_rows.load(Ordering::Relaxed);
+
+            assert_eq!(repeated == 23, all_repeated, "{} rows repeated", repeated);
+            assert!(imgbuf.enumerate_pixels().all(|(x, y, pixel)| *pixel == sample_color(grid.samples_at(x, y), &params)));
+
+            let path = std::env::temp_dir().join(format!("mandelbrot-rows-{}-{}.png", repeated, std::process::id()));
+            imgbuf.save(&path).unwrap();
+            assert_eq!(image::open(&path).unwrap().into_rgb8(), imgbuf);
+            std::fs::remove_file(&path).unwrap();
+        }
+    }
+
+    #[test]
+    fn refining_an_exported_grid_matches_a_direct_render() {
+        let coarse = RenderBuilder::new().dimensions(32, 24).center(-0.6, 0.2).radius(1.5).iterations(80).sample_offset(0.0).build().unwrap();
+        let path = std::env::temp_dir().join(format!("mandelbrot-refine-{}.grid", std::process::id()));
+        let path = path.to_str().unwrap();
+
+        crate::gridfile::write_grid(path, &compute_grid(&coarse, |_| {}).unwrap(), &coarse).unwrap();
+        let (header, grid) = crate::gridfile::read_grid(path).unwrap();
+        std::fs::remove_file(path).unwrap();
+
+        let fine = RenderParams { width: 64, height: 48, color: true, smooth: true, ..coarse.clone() };
+        let stats = Arc::new(RenderStats::default());
+        let prior = PriorGrid::new(header.apply(&fine), grid.clone(), &fine).unwrap();
+        let refined = RenderParams { prior: Some(Arc::new(prior)), stats: Some(stats.clone()), ..fine.clone() };
+
+        assert_eq!(render(&refined, |_| {}).unwrap(), render(&fine, |_| {}).unwrap());
+        assert_eq!(stats.reused_samples.load(Ordering::Relaxed), 32 * 24);
+
+        // Centered samples of a 2× upscale all fall between the prior ones.
+        let centered = RenderParams { sample_offset: 0.5, ..fine.clone() };
+        let view = RenderParams { sample_offset: 0.5, ..header.apply(&fine) };
+        assert!(matches!(PriorGrid::new(view, grid, &centered), Err(MandelbrotError::InvalidParameter(_))));
+
+        // Samples of log-polar or masked grids do not land where the linear
+        // full depth render would take them.
+        let log_polar = RenderParams { mapping: Mapping::LogPolar { log_radius: (-3.0, 0.5) }, ..coarse.clone() };
+        let masked = RenderParams { iteration_mask: Some(Arc::new(vec![20; 32 * 24])), ..coarse.clone() };
+
+        for view in [log_polar, masked] {
+            crate::gridfile::write_grid(path, &compute_grid(&view, |_| {}).unwrap(), &view).unwrap();
+            let (header, grid) = crate::gridfile::read_grid(path).unwrap();
+            std::fs::remove_file(path).unwrap();
+
+            assert_eq!(header.apply(&fine).mapping, view.mapping);
+            assert_eq!(header.apply(&fine).iteration_mask, view.iteration_mask);
+
+            assert!(matches!(PriorGrid::new(header.apply(&fine), grid, &fine), Err(MandelbrotError::InvalidParameter(_))));
+        }
+    }
+
+    #[test]
+    fn optimizations_do_not_change_the_output() {
+        // Views across the whole set, inside the cardioid, through the period
+        // two bulb and of a Julia set mirrored about the origin.
+        let views = [
+            RenderBuilder::new().center(-0.75, 0.0).radius(2.5),
+            RenderBuilder::new().center(-0.2, 0.0).radius(0.8),
+            RenderBuilder::new().center(-1.0, 0.0).radius(0.4),
+            RenderBuilder::new().fractal(Fractal::Julia).julia(-0.8, 0.156).center(0.0, 0.0).radius(3.0)
+        ];
+
+        // A bailout just above 1 lets parts of the cardioid and the bulb
+        // escape, and the other shapes bend where orbits leave.
+        let bailouts = [(BailoutShape::Circle, 2.0), (BailoutShape::Circle, 1.1), (BailoutShape::Square, 2.0), (BailoutShape::Diamond, 2.0)];
+
+        for view in views {
+            for (shape, bailout) in bailouts {
+                let optimized = view.clone().dimensions(64, 48).iterations(200).bailout_shape(shape).bailout(bailout)
+                    .color(true).smooth(true).tile_size(8).build().unwrap();
+                let reference = RenderParams { fast_paths: false, symmetry: false, ..optimized.clone() };
+
+                assert_eq!(
+                    render(&optimized, |_| {}).unwrap(), render(&reference, |_| {}).unwrap(),
+                    "view at {:?} with a {:?} bailout of {}", optimized.center, shape, bailout
+                );
+            }
+        }
+    }
+}
@@ -0,0 +1,133 @@
+use crate::error::MandelbrotError;
+use crate::mandelbrot::IndexChannel;
+use crate::render::{compute_grid, EscapeGrid, Mapping, RenderParams};
+
+/// Signed distance from the center of every pixel to the boundary of the
+/// set, in units of the complex plane, row by row. Distances are negative
+/// inside the set and positive outside of it.
+///
+/// Outside the set these are the distance estimates of the escaped orbits,
+/// which only match the true distance up to a small factor but grow
+/// steadily away from the set. Iterating says nothing about how deep inside a point lies,
+/// so interior distances are measured to the nearest escaped pixel instead
+/// and only resolve whole pixels.
+pub fn signed_distances(params: &RenderParams) -> Result<Vec<f32>, MandelbrotError> {
+    if params.mapping != Mapping::Linear {
+        return Err(MandelbrotError::InvalidParameter("signed distances need the linear mapping".to_string()));
+    }
+
+    let params = RenderParams { index_channel: IndexChannel::Distance, samples: 1, sample_offset: 0.5, ..params.clone() };
+    let grid = compute_grid(&params, |_| {})?;
+    let pixel = params.radius.0 / params.width as f32;
+
+    let interior = interior_distances(&grid);
+
+    Ok((0..grid.len()).map(|index| match interior[index] {
+        Some(distance) => -distance * pixel,
+        None => grid.channel[index]
+    }).collect())
+}
+
+/// Distance in pixels from every pixel inside the set to the nearest one
+/// outside of it, `None` for pixels outside. Views entirely inside the set
+/// report the length of the image diagonal.
+fn interior_distances(grid: &EscapeGrid) -> Vec<Option<f32>> {
+    let (width, height) = (grid.width as usize, grid.height as usize);
+    let far = (width * width + height * height) as f32;
+
+    // Exact squared Euclidean distance transform, first along every column
+    // and then along every row of the column results.
+    let mut squared = vec![0.0; width * height];
+    let mut line = Vec::with_capacity(width.max(height));
+
+    for x in 0..width {
+        line.clear();
+        line.extend((0..height).map(|y| if grid.in_set[y * width + x] { far } else { 0.0 }));
+
+        for (y, distance) in squared_distances(&line).into_iter().enumerate() {
+            squared[y * width + x] = distance;
+        }
+    }
+
+    for y in 0..height {
+        let row = &mut squared[y * width..(y + 1) * width];
+        let distances = squared_distances(row);
+        row.copy_from_slice(&distances);
+    }
+
+    squared.into_iter().enumerate().map(|(index, distance)| {
+        if grid.in_set[index] { Some(distance.sqrt()) } else { None }
+    }).collect()
+}
+
+/// One dimensional pass of Felzenszwalb and Huttenlocher's distance
+/// transform: the lower envelope of the parabolas `(i - q)² + f(q)`.
+fn squared_distances(f: &[f32]) -> Vec<f32> {
+    let n = f.len();
+    let mut vertices = vec![0; n];
+    let mut bounds = vec![0.0; n + 1];
+    let mut k = 0;
+
+    bounds[0] = f32::NEG_INFINITY;
+    bounds[1] = f32::INFINITY;
+
+    for q in 1..n {
+        let intersection = |v: usize| ((f[q] + (q * q) as f32) - (f[v] + (v * v) as f32)) / (2 * q - 2 * v) as f32;
+        let mut s = intersection(vertices[k]);
+
+        while s <= bounds[k] {
+            k -= 1;
+            s = intersection(vertices[k]);
+        }
+
+        k += 1;
+        vertices[k] = q;
+        bounds[k] = s;
+        bounds[k + 1] = f32::INFINITY;
+    }
+
+    k = 0;
+
+    (0..n).map(|q| {
+        while bounds[k + 1] < q as f32 {
+            k += 1;
+        }
+
+        let offset = q as f32 - vertices[k] as f32;
+        offset * offset + f[vertices[k]]
+    }).collect()
+}
+
+/// Signed distances as a 32-bit float image with the same value in every
+/// channel, e.g. for saving as OpenEXR.
+pub fn sdf_image(params: &RenderParams) -> Result<image::Rgb32FImage, MandelbrotError> {
+    let distances = signed_distances(params)?;
+
+    Ok(image::ImageBuffer::from_fn(params.width, params.height, |x, y| {
+        let distance = distances[y as usize * params.width as usize + x as usize];
+        image::Rgb([distance, distance, distance])
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn distances_are_negative_inside_and_grow_outside() {
+        let params = crate::RenderBuilder::new().dimensions(80, 60).center(-0.75, 0.0).radius(4.0).iterations(100).build().unwrap();
+        let distances = signed_distances(&params).unwrap();
+        let at = |x: u32, y: u32| distances[(y * 80 + x) as usize];
+        let pixel = params.radius.0 / 80.0;
+
+        // The main cardioid is around the middle, the corners lie far outside.
+        let (cx, cy) = params.pixel(num::complex::Complex::new(-0.2, 0.0));
+        assert!(at(cx as u32, cy as u32) < -pixel, "{}", at(cx as u32, cy as u32));
+        assert!(at(0, 0) > 0.5 && at(79, 59) > 0.5, "{} {}", at(0, 0), at(79, 59));
+
+        let grid = compute_grid(&RenderParams { samples: 1, sample_offset: 0.5, ..params.clone() }, |_| {}).unwrap();
+        assert!(grid.in_set.iter().zip(&distances).all(|(&in_set, &distance)| (distance < 0.0) == in_set));
+
+        assert!(signed_distances(&RenderParams { mapping: Mapping::LogPolar { log_radius: (-2.0, 0.0) }, ..params }).is_err());
+    }
+}
@@ -0,0 +1,87 @@
+use num::complex::Complex;
+
+use crate::builder::RenderBuilder;
+use crate::error::MandelbrotError;
+use crate::mandelbrot::escape;
+use crate::render::render;
+
+/// Size of the scene rendered by the self-test.
+pub const SELFTEST_SIZE: (u32, u32) = (64, 48);
+
+/// FNV-1a hash of the self-test scene on a build known to be correct.
+/// `None` skips the checksum comparison.
+pub const SELFTEST_CHECKSUM: Option<u64> = Some(0x1999_a7d6_8a49_8545);
+
+/// Outcome of a single self-test check.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Check {
+    pub name: &'static str,
+    pub passed: bool,
+    pub detail: String
+}
+
+impl Check {
+    fn new(name: &'static str, passed: bool, detail: String) -> Check {
+        Check { name, passed, detail }
+    }
+}
+
+/// FNV-1a hash over the raw bytes of an image.
+pub fn checksum(bytes: &[u8]) -> u64 {
+    bytes.iter().fold(0xcbf2_9ce4_8422_2325, |hash, &byte| (hash ^ byte as u64).wrapping_mul(0x0100_0000_01b3))
+}
+
+/// Renders a small fixed scene and checks a few invariants every correct
+/// build has to uphold.
+pub fn run_selftest() -> Result<Vec<Check>, MandelbrotError> {
+    let params = RenderBuilder::new()
+        .dimensions(SELFTEST_SIZE.0, SELFTEST_SIZE.1)
+        .center(-0.75, 0.0)
+        .radius(1.5)
+        .iterations(64)
+        .threads(1)
+        .color(true)
+        .build()?;
+
+    let origin = escape(Complex::new(0.0, 0.0), &params);
+    let far = escape(Complex::new(2.0, 2.0), &params);
+
+    let mut checks = vec![
+        Check::new("origin is in the set", origin.in_set, format!("{} iterations", origin.iterations)),
+        Check::new("2+2i escapes immediately", !far.in_set && far.iterations <= 1, format!("{} iterations", far.iterations))
+    ];
+
+    let image = render(&params, |_| {})?;
+
+    checks.push(Check::new(
+        "image has the expected dimensions",
+        image.dimensions() == SELFTEST_SIZE,
+        format!("{}x{}", image.width(), image.height())
+    ));
+
+    if let Some(expected) = SELFTEST_CHECKSUM {
+        let actual = checksum(image.as_raw());
+        checks.push(Check::new("image matches the known checksum", actual == expected, format!("{:016x}", actual)));
+    }
+
+    Ok(checks)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn every_check_passes() {
+        let checks = run_selftest().unwrap();
+
+        assert_eq!(checks.len(), 3 + SELFTEST_CHECKSUM.is_some() as usize);
+        assert!(checks.iter().all(|check| check.passed), "{:?}", checks);
+    }
+
+    #[test]
+    fn checksum_is_fnv1a() {
+        assert_eq!(checksum(b""), 0xcbf2_9ce4_8422_2325);
+        assert_eq!(checksum(b"a"), 0xaf63_dc4c_8601_ec8c);
+    }
+}
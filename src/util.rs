@@ -1,7 +1,506 @@
-pub fn parse_tuple<T: std::str::FromStr>(dimensions: String, delimiter: &str) -> (T, T) {
-    let mut result: Vec<T> = dimensions.split(delimiter).take(2).map(|s| {
-        s.parse::<T>().ok().unwrap()
+use crate::error::MandelbrotError;
+
+pub fn parse_tuple<T: std::str::FromStr>(input: &str, delimiter: &str) -> Result<(T, T), MandelbrotError> {
+    let mut result = input.split(delimiter).map(|s| {
+        s.trim().parse::<T>().map_err(|_| {
+            MandelbrotError::Parse(format!("'{}' in '{}'", s, input))
+        })
+    });
+
+    match (result.next(), result.next(), result.next()) {
+        (Some(a), Some(b), None) => Ok((a?, b?)),
+        _ => Err(MandelbrotError::Parse(
+            format!("'{}', expected two values separated by '{}'", input, delimiter)
+        ))
+    }
+}
+
+pub fn parse_color(input: &str) -> Result<image::Rgb<u8>, MandelbrotError> {
+    let channels = input.split(',').map(|s| {
+        s.trim().parse::<u8>().map_err(|_| {
+            MandelbrotError::Parse(format!("color channel '{}' in '{}'", s, input))
+        })
+    }).collect::<Result<Vec<u8>, MandelbrotError>>()?;
+
+    match channels[..] {
+        [r, g, b] => Ok(image::Rgb([r, g, b])),
+        _ => Err(MandelbrotError::Parse(format!("color '{}', expected r,g,b", input)))
+    }
+}
+
+pub fn parse_triple(input: &str) -> Result<[f32; 3], MandelbrotError> {
+    let values = input.split(',').map(|s| {
+        s.trim().parse::<f32>().map_err(|_| {
+            MandelbrotError::Parse(format!("'{}' in '{}'", s, input))
+        })
+    }).collect::<Result<Vec<f32>, MandelbrotError>>()?;
+
+    match values[..] {
+        [a, b, c] => Ok([a, b, c]),
+        _ => Err(MandelbrotError::Parse(format!("'{}', expected three values separated by ','", input)))
+    }
+}
+
+/// Formats a coordinate with just enough decimals to tell neighbouring pixels
+/// apart, given the extent of the view along that axis and its pixel count.
+/// Shallow views get short numbers while deep zooms keep all relevant digits.
+pub fn format_coordinate(value: f32, radius: f32, pixels: u32) -> String {
+    let pixel_size = radius / pixels.max(1) as f32;
+    let decimals = if pixel_size > 0.0 && pixel_size.is_finite() {
+        ((-pixel_size.log10()).ceil().max(0.0) as usize + 1).min(12)
+    } else {
+        6
+    };
+
+    format!("{:.*}", decimals, value)
+}
+
+/// Loads a greyscale mask, resized to the output dimensions, that scales the
+/// iteration depth per pixel: white keeps the full depth, black leaves a
+/// single iteration.
+pub fn load_iteration_mask(path: &str, width: u32, height: u32, iterations: u32) -> Result<Vec<u32>, MandelbrotError> {
+    let mask = image::open(path)?.to_luma8();
+    let mask = image::imageops::resize(&mask, width, height, image::imageops::FilterType::Triangle);
+
+    Ok(mask.pixels().map(|pixel| {
+        ((pixel.0[0] as f32 / 255.0 * iterations as f32).round() as u32).max(1)
+    }).collect())
+}
+
+/// Extracts a color ramp of `count` entries from the pixels of an image,
+/// ordered from dark to bright. The pixels are sorted by luminance and split
+/// into `count` equally sized groups, each of which is averaged into one
+/// entry of the ramp.
+pub fn palette_from_image(imgbuf: &image::RgbImage, count: usize) -> Result<Vec<image::Rgb<u8>>, MandelbrotError> {
+    if count == 0 || imgbuf.width() == 0 || imgbuf.height() == 0 {
+        return Err(MandelbrotError::InvalidParameter("palette needs at least one color and a non-empty image".to_string()));
+    }
+
+    let luminance = |pixel: &image::Rgb<u8>| {
+        0.2126 * pixel[0] as f32 + 0.7152 * pixel[1] as f32 + 0.0722 * pixel[2] as f32
+    };
+
+    let mut pixels: Vec<image::Rgb<u8>> = imgbuf.pixels().copied().collect();
+    pixels.sort_by(|a, b| luminance(a).total_cmp(&luminance(b)));
+
+    Ok((0..count).map(|i| {
+        let start = i * pixels.len() / count;
+        let end = ((i + 1) * pixels.len() / count).max(start + 1);
+        let group = &pixels[start..end];
+
+        let mut sum = [0u64; 3];
+
+        for pixel in group {
+            for (total, channel) in sum.iter_mut().zip(pixel.0) {
+                *total += channel as u64;
+            }
+        }
+
+        let length = group.len() as u64;
+        image::Rgb(sum.map(|total| ((total + length / 2) / length) as u8))
+    }).collect())
+}
+
+/// Iteration depths above this are likely to take a very long time to render.
+pub const ITERATION_WARNING_THRESHOLD: u32 = 100_000;
+
+pub fn iteration_warning(iterations: u32) -> Option<String> {
+    if iterations == 0 {
+        Some("an iteration depth of 0 means no point can escape, so the image will be entirely black".to_string())
+    } else if iterations > ITERATION_WARNING_THRESHOLD {
+        Some(format!(
+            "an iteration depth of {} is very high and the render may take extremely long",
+            iterations
+        ))
+    } else {
+        None
+    }
+}
+
+/// Returns true if every pixel is within `tolerance` of the first one on each
+/// channel, i.e. the image is essentially a single flat color.
+pub fn is_uniform(imgbuf: &image::RgbImage, tolerance: u8) -> bool {
+    let mut pixels = imgbuf.pixels();
+
+    let first = match pixels.next() {
+        Some(pixel) => *pixel,
+        None => return true
+    };
+
+    pixels.all(|pixel| {
+        pixel.0.iter().zip(first.0).all(|(&a, b)| a.abs_diff(b) <= tolerance)
+    })
+}
+
+pub fn uniform_image_hint(imgbuf: &image::RgbImage, center_in_set: bool) -> Option<&'static str> {
+    if !is_uniform(imgbuf, 2) {
+        return None;
+    }
+
+    if center_in_set {
+        Some("the output is a single color, the viewport may be fully inside the set - try a larger radius or a different center")
+    } else {
+        Some("the output is a single color, the viewport may be fully outside the set - try a different center or more iterations")
+    }
+}
+
+pub fn draw_crosshair(imgbuf: &mut image::RgbImage, color: image::Rgb<u8>) {
+    let (width, height) = imgbuf.dimensions();
+    let (cx, cy) = (width / 2, height / 2);
+
+    for x in 0..width {
+        imgbuf.put_pixel(x, cy, color);
+    }
+
+    for y in 0..height {
+        imgbuf.put_pixel(cx, y, color);
+    }
+}
+
+/// Draws a small cross with arms of `size` pixels centered on `(x, y)`,
+/// clipped to the image.
+pub fn draw_marker(imgbuf: &mut image::RgbImage, x: i64, y: i64, size: i64, color: image::Rgb<u8>) {
+    let (width, height) = imgbuf.dimensions();
+
+    for offset in -size..=size {
+        for (px, py) in [(x + offset, y), (x, y + offset)] {
+            if px >= 0 && py >= 0 && px < width as i64 && py < height as i64 {
+                imgbuf.put_pixel(px as u32, py as u32, color);
+            }
+        }
+    }
+}
+
+pub fn thumbnail_name(name: &str) -> String {
+    let path = std::path::Path::new(name);
+    let stem = path.file_stem().and_then(|s| s.to_str()).unwrap_or(name);
+
+    path.with_file_name(format!("{}.thumb.png", stem))
+        .to_string_lossy()
+        .into_owned()
+}
+
+/// Integer dimensions closest to the given pixel budget at an aspect ratio
+/// of `aspect.0:aspect.1`.
+pub fn megapixel_dimensions(megapixels: f32, aspect: (f32, f32)) -> Result<(u32, u32), MandelbrotError> {
+    if !(megapixels.is_finite() && megapixels > 0.0) {
+        return Err(MandelbrotError::InvalidParameter(format!("megapixels must be positive, got {}", megapixels)));
+    }
+
+    if !(aspect.0 > 0.0 && aspect.1 > 0.0) {
+        return Err(MandelbrotError::InvalidParameter(format!("invalid aspect ratio {}:{}", aspect.0, aspect.1)));
+    }
+
+    let height = (megapixels as f64 * 1e6 * aspect.1 as f64 / aspect.0 as f64).sqrt();
+    let width = height * aspect.0 as f64 / aspect.1 as f64;
+
+    Ok(((width.round() as u32).max(1), (height.round() as u32).max(1)))
+}
+
+/// Scales the dimensions so the larger one equals `size`, preserving the
+/// aspect ratio.
+pub fn fit_dimensions(width: u32, height: u32, size: u32) -> (u32, u32) {
+    if width >= height {
+        (size, ((size as f32 * height as f32 / width as f32).round() as u32).max(1))
+    } else {
+        (((size as f32 * width as f32 / height as f32).round() as u32).max(1), size)
+    }
+}
+
+pub fn make_thumbnail(imgbuf: &image::RgbImage, size: u32) -> image::RgbImage {
+    let (width, height) = imgbuf.dimensions();
+    let (thumb_width, thumb_height) = fit_dimensions(width, height, size);
+
+    image::imageops::resize(imgbuf, thumb_width, thumb_height, image::imageops::FilterType::Lanczos3)
+}
+
+pub fn suffixed_name(name: &str, suffix: &str) -> String {
+    let path = std::path::Path::new(name);
+    let stem = path.file_stem().and_then(|s| s.to_str()).unwrap_or(name);
+
+    let file_name = match path.extension().and_then(|s| s.to_str()) {
+        Some(extension) => format!("{}{}.{}", stem, suffix, extension),
+        None => format!("{}{}", stem, suffix)
+    };
+
+    path.with_file_name(file_name).to_string_lossy().into_owned()
+}
+
+/// Delay before the first retry of a failed save, doubled for every further
+/// attempt.
+pub const SAVE_RETRY_DELAY: std::time::Duration = std::time::Duration::from_millis(200);
+
+/// Runs `save` until it succeeds, retrying up to `retries` times after I/O
+/// errors, which may be transient on network filesystems. Any other error is
+/// returned right away.
+pub fn save_with_retries<F: FnMut() -> Result<(), MandelbrotError>>(retries: u32, mut save: F) -> Result<(), MandelbrotError> {
+    let mut delay = SAVE_RETRY_DELAY;
+
+    for _ in 0..retries {
+        match save() {
+            Err(MandelbrotError::Io(err)) | Err(MandelbrotError::Image(image::ImageError::IoError(err))) => {
+                eprintln!("Warning: saving failed ({}), retrying in {}ms", err, delay.as_millis());
+                std::thread::sleep(delay);
+                delay *= 2;
+            },
+            result => return result
+        }
+    }
+
+    save()
+}
+
+pub fn layer_levels(iterations: u32, layers: u32) -> Vec<u32> {
+    (1..=layers as u64).map(|i| {
+        ((i * iterations as u64) / layers as u64).max(1) as u32
+    }).collect()
+}
+
+#[derive(clap::ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SamplePattern {
+    Grid,
+    RotatedGrid,
+    Poisson
+}
+
+/// Small xorshift generator so sample patterns are reproducible without
+/// pulling in a dependency for random numbers.
+struct XorShift(u64);
+
+impl XorShift {
+    fn next_f32(&mut self) -> f32 {
+        self.0 ^= self.0 << 13;
+        self.0 ^= self.0 >> 7;
+        self.0 ^= self.0 << 17;
+
+        (self.0 >> 40) as f32 / (1u64 << 24) as f32
+    }
+}
+
+/// Returns `count` sub-pixel offsets in `[0, 1)²` laid out according to the
+/// given pattern. Grid samples sit at `cell_offset` within their cells, so
+/// 0.5 samples cell centers and 0 their top-left corners.
+pub fn sample_offsets(pattern: SamplePattern, count: u32, cell_offset: f32) -> Vec<(f32, f32)> {
+    let count = count.max(1) as usize;
+    let side = (count as f32).sqrt().ceil() as usize;
+
+    let grid: Vec<(f32, f32)> = (0..count).map(|i| {
+        ((i % side) as f32 / side as f32, (i / side) as f32 / side as f32)
     }).collect();
 
-    (result.remove(0), result.remove(0))
+    match pattern {
+        SamplePattern::Grid => grid.into_iter().map(|(x, y)| {
+            (x + cell_offset / side as f32, y + cell_offset / side as f32)
+        }).collect(),
+        SamplePattern::RotatedGrid => {
+            let (sin, cos) = 0.5f32.atan().sin_cos();
+            let shift = 0.5 / side as f32;
+
+            grid.into_iter().map(|(x, y)| {
+                let (dx, dy) = (x + shift - 0.5, y + shift - 0.5);
+
+                (
+                    (dx * cos - dy * sin + 0.5).rem_euclid(1.0),
+                    (dx * sin + dy * cos + 0.5).rem_euclid(1.0)
+                )
+            }).collect()
+        },
+        SamplePattern::Poisson => {
+            let mut rng = XorShift(0x2545_f491_4f6c_dd1d);
+            let mut min_distance = 0.75 / (count as f32).sqrt();
+            let mut points: Vec<(f32, f32)> = Vec::with_capacity(count);
+            let mut attempts = 0;
+
+            while points.len() < count {
+                let candidate = (rng.next_f32(), rng.next_f32());
+
+                let fits = points.iter().all(|&(x, y)| {
+                    let (dx, dy) = (x - candidate.0, y - candidate.1);
+                    dx * dx + dy * dy >= min_distance * min_distance
+                });
+
+                if fits && !points.contains(&candidate) {
+                    points.push(candidate);
+                    attempts = 0;
+                } else {
+                    attempts += 1;
+
+                    if attempts > 1000 {
+                        min_distance *= 0.9;
+                        attempts = 0;
+                    }
+                }
+            }
+
+            points
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn crosshair_marks_the_center_row_and_column() {
+        let red = image::Rgb([255, 0, 0]);
+        let mut imgbuf = image::RgbImage::new(9, 6);
+
+        draw_crosshair(&mut imgbuf, red);
+
+        for (x, y, pixel) in imgbuf.enumerate_pixels() {
+            assert_eq!(*pixel == red, x == 4 || y == 3, "pixel ({}, {})", x, y);
+        }
+    }
+
+    #[test]
+    fn thumbnail_fits_the_size_and_keeps_the_aspect_ratio() {
+        for (width, height) in [(400, 300), (300, 400), (250, 250)] {
+            let thumbnail = make_thumbnail(&image::RgbImage::new(width, height), 100);
+            let (thumb_width, thumb_height) = thumbnail.dimensions();
+
+            assert_eq!(thumb_width.max(thumb_height), 100);
+            assert!((thumb_width as f32 / thumb_height as f32 - width as f32 / height as f32).abs() < 0.02);
+        }
+    }
+
+    #[test]
+    fn thumbnail_name_replaces_the_extension() {
+        assert_eq!(thumbnail_name("out/fractal.png"), "out/fractal.thumb.png");
+    }
+
+    #[test]
+    fn sample_patterns_give_distinct_offsets_in_the_unit_square() {
+        for pattern in [SamplePattern::Grid, SamplePattern::RotatedGrid, SamplePattern::Poisson] {
+            for count in [1, 4, 5, 9, 16] {
+                let offsets = sample_offsets(pattern, count, 0.5);
+
+                assert_eq!(offsets.len(), count as usize, "{:?}", pattern);
+                assert!(offsets.iter().all(|&(x, y)| (0.0..1.0).contains(&x) && (0.0..1.0).contains(&y)), "{:?}", pattern);
+
+                for (i, a) in offsets.iter().enumerate() {
+                    assert!(!offsets[i + 1..].contains(a), "{:?} repeats {:?}", pattern, a);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn single_grid_sample_sits_at_the_cell_offset() {
+        assert_eq!(sample_offsets(SamplePattern::Grid, 1, 0.5), [(0.5, 0.5)]);
+        assert_eq!(sample_offsets(SamplePattern::Grid, 4, 0.0), [(0.0, 0.0), (0.5, 0.0), (0.0, 0.5), (0.5, 0.5)]);
+    }
+
+    #[test]
+    fn zero_iterations_warn_that_nothing_escapes() {
+        assert!(iteration_warning(0).unwrap().contains("no point can escape"));
+
+        let params = crate::RenderBuilder::new().dimensions(8, 8).iterations(0).build().unwrap();
+        assert!(is_uniform(&crate::render(&params, |_| {}).unwrap(), 0));
+    }
+
+    #[test]
+    fn huge_iteration_depths_warn() {
+        assert!(iteration_warning(ITERATION_WARNING_THRESHOLD + 1).unwrap().contains("very high"));
+        assert_eq!(iteration_warning(ITERATION_WARNING_THRESHOLD), None);
+        assert_eq!(iteration_warning(32), None);
+    }
+
+    #[test]
+    fn all_interior_render_hints_at_the_viewport() {
+        let params = crate::RenderBuilder::new().dimensions(32, 32).center(-0.1, 0.0).radius(0.01).iterations(64).build().unwrap();
+        let imgbuf = crate::render::render(&params, |_| {}).unwrap();
+        let hint = uniform_image_hint(&imgbuf, true).expect("an all interior render is a single color");
+
+        assert!(hint.contains("fully inside the set"), "{}", hint);
+
+        let params = crate::RenderBuilder::new().dimensions(32, 32).iterations(64).build().unwrap();
+        assert_eq!(uniform_image_hint(&crate::render::render(&params, |_| {}).unwrap(), true), None);
+    }
+
+    #[test]
+    fn coordinates_keep_the_digits_of_the_zoom_level() {
+        assert_eq!(format_coordinate(-0.75, 3.0, 1000), "-0.7500");
+        assert_eq!(format_coordinate(-0.75, 3e-4, 1000), "-0.75000000");
+
+        for radius in [4.0, 0.1, 1e-3] {
+            let pixel = radius / 800.0;
+            assert_ne!(format_coordinate(0.25, radius, 800), format_coordinate(0.25 + pixel, radius, 800));
+        }
+    }
+
+    #[test]
+    fn white_masks_keep_the_depth_and_black_ones_leave_one_iteration() {
+        let params = crate::RenderBuilder::new().dimensions(40, 30).iterations(64).build().unwrap();
+        let path = std::env::temp_dir().join(format!("mandelbrot-mask-{}.png", std::process::id()));
+        let path = path.to_str().unwrap();
+
+        let masked = |level: u8| {
+            image::GrayImage::from_pixel(20, 15, image::Luma([level])).save(path).unwrap();
+            let mask = load_iteration_mask(path, 40, 30, params.iterations).unwrap();
+            let params = crate::render::RenderParams { iteration_mask: Some(std::sync::Arc::new(mask)), ..params.clone() };
+
+            crate::render::compute_grid(&params, |_| {}).unwrap()
+        };
+
+        let (white, black) = (masked(255), masked(0));
+        std::fs::remove_file(path).unwrap();
+
+        let full = crate::render::compute_grid(&params, |_| {}).unwrap();
+
+        assert_eq!(white.escapes().collect::<Vec<_>>(), full.escapes().collect::<Vec<_>>());
+        assert!(black.escapes().all(|escape| escape.iterations <= 1));
+    }
+
+    #[test]
+    fn megapixel_dimensions_hit_the_budget_at_the_aspect_ratio() {
+        for (megapixels, aspect) in [(2.0, (16.0, 9.0)), (12.0, (4.0, 3.0)), (0.5, (1.0, 1.0)), (1.0, (9.0, 16.0))] {
+            let (width, height) = megapixel_dimensions(megapixels, aspect).unwrap();
+
+            assert!(((width * height) as f32 / 1e6 - megapixels).abs() / megapixels < 0.01, "{}x{}", width, height);
+            assert!((width as f32 / height as f32 - aspect.0 / aspect.1).abs() < 0.01, "{}x{}", width, height);
+        }
+
+        assert_eq!(megapixel_dimensions(2.0736, (16.0, 9.0)).unwrap(), (1920, 1080));
+        assert!(megapixel_dimensions(0.0, (1.0, 1.0)).is_err());
+        assert!(megapixel_dimensions(1.0, (0.0, 1.0)).is_err());
+    }
+
+    #[test]
+    fn saves_are_retried_the_configured_number_of_times() {
+        let failing = || MandelbrotError::Io(std::io::Error::new(std::io::ErrorKind::Interrupted, "share went away"));
+
+        let mut attempts = 0;
+        let result = save_with_retries(2, || { attempts += 1; Err(failing()) });
+        assert!(matches!(result, Err(MandelbrotError::Io(_))));
+        assert_eq!(attempts, 3);
+
+        let mut attempts = 0;
+        save_with_retries(2, || { attempts += 1; if attempts == 1 { Err(failing()) } else { Ok(()) } }).unwrap();
+        assert_eq!(attempts, 2);
+
+        let mut attempts = 0;
+        let result = save_with_retries(2, || { attempts += 1; Err(MandelbrotError::InvalidParameter("not transient".to_string())) });
+        assert!(matches!(result, Err(MandelbrotError::InvalidParameter(_))));
+        assert_eq!(attempts, 1);
+    }
+
+    #[test]
+    fn palettes_from_solid_and_gradient_images() {
+        let teal = image::Rgb([20, 140, 150]);
+        assert_eq!(palette_from_image(&image::RgbImage::from_pixel(30, 20, teal), 4).unwrap(), vec![teal; 4]);
+
+        // Shades scattered all over the image still end up in order.
+        let ramp = image::ImageBuffer::from_fn(64, 8, |x, y| {
+            let shade = ((x * 37 + y * 11) % 64 * 4) as u8;
+            image::Rgb([shade, shade / 2, 255 - shade])
+        });
+        let palette = palette_from_image(&ramp, 8).unwrap();
+
+        assert_eq!(palette.len(), 8);
+        assert!(palette.windows(2).all(|pair| pair[0][0] < pair[1][0] && pair[0][2] > pair[1][2]), "{:?}", palette);
+        assert!(palette[0][0] < 32 && palette[7][0] > 220);
+
+        assert!(palette_from_image(&ramp, 0).is_err());
+    }
 }
@@ -0,0 +1,167 @@
+use std::path::PathBuf;
+use std::process::{Command, Output};
+
+/// An empty directory of its own for the files of one test.
+fn scratch_dir(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("mandelbrot-{}-{}", name, std::process::id()));
+    let _ = std::fs::remove_dir_all(&dir);
+    std::fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+fn mandelbrot(args: &[&str]) -> Output {
+    Command::new(env!("CARGO_BIN_EXE_mandelbrot")).args(args).output().unwrap()
+}
+
+fn file_names(dir: &PathBuf) -> Vec<String> {
+    let mut names: Vec<String> = std::fs::read_dir(dir).unwrap()
+        .map(|entry| entry.unwrap().file_name().to_string_lossy().into_owned())
+        .collect();
+
+    names.sort();
+    names
+}
+
+#[test]
+fn layers_are_saved_under_zero_padded_iteration_depths() {
+    let dir = scratch_dir("layers");
+    let name = dir.join("fractal.png");
+
+    let output = mandelbrot(&["-d", "40x30", "-i", "30", "--layers", "3", name.to_str().unwrap()]);
+
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+    assert_eq!(file_names(&dir), ["fractal_iter_0010.png", "fractal_iter_0020.png", "fractal_iter_0030.png"]);
+
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn radius_x_needs_radius_y() {
+    let output = mandelbrot(&["-d", "40x30", "--radius-x", "1.5", "unused.png"]);
+
+    assert_eq!(output.status.code(), Some(2));
+    assert!(String::from_utf8_lossy(&output.stderr).contains("--radius-y"));
+}
+
+#[test]
+fn radius_x_conflicts_with_radius() {
+    let output = mandelbrot(&["-d", "40x30", "--radius", "1", "--radius-x", "1.5", "--radius-y", "1", "unused.png"]);
+
+    assert_eq!(output.status.code(), Some(2));
+}
+
+#[test]
+fn preview_renders_small_whatever_the_dimensions() {
+    let dir = scratch_dir("preview");
+    let name = dir.join("preview.png");
+
+    let output = mandelbrot(&["-d", "4000x3000", "-i", "100000", "--preview", name.to_str().unwrap()]);
+
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+    assert!(String::from_utf8_lossy(&output.stdout).contains("4000x3000"));
+    assert_eq!(image::image_dimensions(&name).unwrap(), (256, 192));
+
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn also_greyscale_saves_the_greyscale_coloring_of_the_same_grid() {
+    let dir = scratch_dir("also-greyscale");
+    let (name, plain) = (dir.join("fractal.png"), dir.join("plain.png"));
+
+    let output = mandelbrot(&["-d", "40x30", "-i", "50", "--color", "--also-greyscale", name.to_str().unwrap()]);
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+
+    let output = mandelbrot(&["-d", "40x30", "-i", "50", plain.to_str().unwrap()]);
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+
+    assert_eq!(file_names(&dir), ["fractal.grey.png", "fractal.png", "plain.png"]);
+
+    let grey = image::open(dir.join("fractal.grey.png")).unwrap().into_rgb8();
+    assert_eq!(grey, image::open(&plain).unwrap().into_rgb8());
+    assert_ne!(grey, image::open(&name).unwrap().into_rgb8());
+
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn json_progress_counts_up_to_the_total() {
+    let dir = scratch_dir("progress-json");
+    let (name, progress) = (dir.join("fractal.png"), dir.join("progress.jsonl"));
+
+    let output = mandelbrot(&[
+        "-d", "400x300", "-i", "2000", "--threads", "2", "--progress-json", progress.to_str().unwrap(), name.to_str().unwrap()
+    ]);
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+
+    let lines: Vec<serde_json::Value> = std::fs::read_to_string(&progress).unwrap().lines()
+        .map(|line| serde_json::from_str(line).unwrap())
+        .collect();
+    let done: Vec<u64> = lines.iter().map(|line| line["done"].as_u64().unwrap()).collect();
+
+    assert!(lines.iter().all(|line| line["total"] == 400 * 300 && line["eta_secs"].as_f64().unwrap() >= 0.0));
+    assert!(done.windows(2).all(|pair| pair[0] < pair[1]), "{:?}", done);
+    assert_eq!(done.last(), Some(&(400 * 300)));
+
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn timed_out_renders_exit_with_code_five_and_save_nothing() {
+    let dir = scratch_dir("timeout");
+    let name = dir.join("fractal.png");
+
+    let output = mandelbrot(&[
+        "-d", "2000x2000", "-i", "100000", "--no-optimizations", "--timeout", "0.2", name.to_str().unwrap()
+    ]);
+
+    assert_eq!(output.status.code(), Some(5), "{}", String::from_utf8_lossy(&output.stderr));
+    assert!(String::from_utf8_lossy(&output.stderr).contains("timeout"));
+    assert!(file_names(&dir).is_empty());
+
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn selftest_passes() {
+    let output = mandelbrot(&["--selftest"]);
+
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stdout));
+    assert!(!String::from_utf8_lossy(&output.stdout).contains("FAIL"));
+}
+
+#[test]
+fn goto_renders_the_bookmarked_view() {
+    let dir = scratch_dir("bookmarks");
+    let (file, saved, restored) = (dir.join("bookmarks.jsonl"), dir.join("saved.png"), dir.join("restored.png"));
+    let file = file.to_str().unwrap();
+
+    let output = mandelbrot(&[
+        "-d", "40x30", "-c=-0.7436,0.1318", "-r", "0.01", "-i", "300", "--bookmark", file, "--label", "spiral", saved.to_str().unwrap()
+    ]);
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+
+    let output = mandelbrot(&["-d", "40x30", "--bookmark", file, "--goto", "spiral", restored.to_str().unwrap()]);
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+
+    assert_eq!(image::open(&saved).unwrap().into_rgb8(), image::open(&restored).unwrap().into_rgb8());
+
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn reproducible_renders_match_whatever_the_thread_count() {
+    let dir = scratch_dir("reproducible");
+    let render = |name: &str, extra: &[&str]| {
+        let path = dir.join(name);
+        let output = mandelbrot(&[&["-d", "60x40", "-i", "200", "--color", "--smooth", path.to_str().unwrap()], extra].concat());
+
+        assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+        image::open(&path).unwrap().into_rgb8()
+    };
+
+    let single = render("threads-1.png", &["--reproducible", "--threads", "1"]);
+    assert_eq!(render("threads-3.png", &["--reproducible", "--threads", "3"]), single);
+
+    std::fs::remove_dir_all(&dir).unwrap();
+}